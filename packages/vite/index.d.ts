@@ -1,12 +1,54 @@
 
 export interface Options {
-    layout: string;
+    /**
+     * Import path of the layout, or named layouts picked with `layout: name` in frontmatter,
+     * where `_` is the default. `layout: false` in frontmatter renders without a layout.
+     */
+    layout?: string | Record<string, string>;
+    /** Globs matched against the file path, mapped to the named layout matching files use */
+    layoutGlobs?: Record<string, string>;
+    /** Elements imported from the layout as uppercased components, e.g. `a` as `A` */
     customTags?: string[];
+    /**
+     * Elements rendered as components, with attributes passed as props. A string names an
+     * export of the layout (named after the element), `from` imports the default export of a
+     * module instead, e.g. `{ a: { component: "Link", from: "$lib/Link.svelte" } }`.
+     */
+    components?: Record<string, string | { component: string; from?: string }>;
+    /** Bundled syntect theme name, e.g. `InspiredGitHub` */
+    theme?: string;
+    /** Emit `hl-*` scope classes instead of inline styles, see `themeCss` */
+    highlightClasses?: boolean;
+    /** Svelte version to generate code for, `svelte4` by default */
+    target?: 'svelte4' | 'svelte5';
+    /** What unsupported math renders as, `code` by default */
+    mathFallback?: 'code' | 'delimited';
+    /**
+     * Link headings to themselves, before or after their text, or by making the text a link.
+     * Map the `headingLink` element in `components` to render links with a component.
+     */
+    headingLinks?: 'none' | 'prepend' | 'append' | 'wrap';
+    /** HTML inside prepended and appended heading links, `#` by default */
+    headingLinkContent?: string;
+    headingLinkClass?: string;
+    /** Lowest heading level in `metadata.toc` and `[[toc]]`, 1 by default */
+    tocMinDepth?: number;
+    /** Highest heading level in `metadata.toc` and `[[toc]]`, 6 by default */
+    tocMaxDepth?: number;
+    /**
+     * Treat markdown as untrusted: svelte tags are text, raw HTML is reduced to safe elements
+     * and attributes, and links can't use protocols such as `javascript:`
+     */
+    safe?: boolean;
+    /** Map generated code back to the markdown, on by default */
+    sourceMap?: boolean;
 }
 
 interface Plugin {
     name: string;
-    markup: (opts: { content: string, filename: string }) => { code: string } | undefined;
+    markup: (opts: { content: string, filename: string }) => { code: string, map?: object } | undefined;
 }
 
-export function mdsvexrs(options: Options): Plugin;
\ No newline at end of file
+export function mdsvexrs(options: Options): Plugin;
+
+export function themeCss(theme?: string): string;
\ No newline at end of file
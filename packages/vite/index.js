@@ -6,22 +6,79 @@ import * as wasm from "mdsvexrs-wasm"
  */
 export function mdsvexrs(options) {
     let opts = wasm.get_default_options()
-    opts.layout = options.layout
+    if (typeof options.layout === "string") {
+        opts.layout = options.layout
+    } else if (options.layout) {
+        // mdsvex style named layouts, `_` is the default
+        for (const [name, layout] of Object.entries(options.layout)) {
+            if (name === "_") opts.layout = layout
+            else opts.add_layout(name, layout)
+        }
+    }
+    if (options.layoutGlobs) {
+        for (const [glob, name] of Object.entries(options.layoutGlobs)) {
+            opts.add_layout_glob(glob, name)
+        }
+    }
     if (options.customTags) {
         options.customTags.forEach(tag => {
             opts.add_custom_tag(tag)
         })
     }
+    if (options.components) {
+        for (const [tag, component] of Object.entries(options.components)) {
+            if (typeof component === "string") opts.add_component(tag, component)
+            else opts.add_component(tag, component.component, component.from)
+        }
+    }
+    if (options.theme) opts.theme = options.theme
+    if (options.highlightClasses) opts.highlight_classes = true
+    if (options.target) opts.target = options.target
+    if (options.mathFallback) opts.math_fallback = options.mathFallback
+    if (options.headingLinks) opts.heading_links = options.headingLinks
+    if (options.headingLinkContent !== undefined) opts.heading_link_content = options.headingLinkContent
+    if (options.headingLinkClass) opts.heading_link_class = options.headingLinkClass
+    if (options.tocMinDepth) opts.toc_min_depth = options.tocMinDepth
+    if (options.tocMaxDepth) opts.toc_max_depth = options.tocMaxDepth
+    if (options.safe) opts.safe = true
+    opts.source_map = options.sourceMap !== false
     return {
         name: 'mdsvexrs',
         markup: ({ content, filename }) => {
             if(!filename || !filename.endsWith('.md')) return
 
-            const code = wasm.render(content, opts)
+            let rendered
+            try {
+                opts.path = filename
+                rendered = wasm.render(content, opts)
+            } catch (e) {
+                if (!(e instanceof wasm.RenderError)) throw e
+                const error = new Error(e.line ? `${filename}:${e.line}:${e.column}: ${e.message}` : `${filename}: ${e.message}`)
+                error.filename = filename
+                if (e.line) {
+                    error.loc = { file: filename, line: e.line, column: e.column }
+                    error.start = { line: e.line, column: e.column }
+                    error.end = { line: e.endLine, column: e.endColumn }
+                }
+                throw error
+            }
 
+            for (const warning of rendered.warnings) {
+                const pos = warning.pos ? `:${warning.pos.start.line}:${warning.pos.start.column}` : ""
+                console.warn(`${filename}${pos}: ${warning.message}`)
+            }
             return {
-                code
+                code: rendered.code,
+                map: rendered.map ?? undefined
             }
         }
     }
-}
\ No newline at end of file
+}
+/**
+ * Stylesheet for `highlightClasses` output.
+ * @param {string} [theme] bundled syntect theme name, default theme if omitted
+ * @returns {string}
+ */
+export function themeCss(theme) {
+    return wasm.theme_css(theme ?? "")
+}
@@ -1,5 +1,11 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
-use mdsvexrs::Context;
+use mdsvexrs::{
+    highlight::{HighlightStyle, SyntectHighlighter},
+    Context, CustomTag, MdsvexrsError,
+};
 
 #[cfg(feature = "wee_alloc")]
 #[global_allocator]
@@ -8,8 +14,21 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[wasm_bindgen]
 pub struct Options {
     layout: String,
-    custom_tags: Vec<String>,
-    // path: String,
+    custom_tags: Vec<CustomTag>,
+    theme: String,
+    highlight_classes: bool,
+    target: String,
+    math_fallback: String,
+    heading_links: String,
+    heading_link_content: String,
+    heading_link_class: String,
+    toc_min_depth: u8,
+    toc_max_depth: u8,
+    source_map: bool,
+    layouts: HashMap<String, String>,
+    layout_globs: Vec<(String, String)>,
+    path: String,
+    safe: bool,
 }
 
 #[wasm_bindgen]
@@ -24,26 +43,303 @@ impl Options {
         self.layout = layout;
     }
 
+    /// What unsupported math renders as: `code` (default) or `delimited`.
+    #[wasm_bindgen(getter)]
+    pub fn math_fallback(&self) -> String {
+        self.math_fallback.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_math_fallback(&mut self, math_fallback: String) {
+        self.math_fallback = math_fallback;
+    }
+
+    /// `none` (default), `prepend`, `append` or `wrap`.
+    #[wasm_bindgen(getter)]
+    pub fn heading_links(&self) -> String {
+        self.heading_links.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_heading_links(&mut self, heading_links: String) {
+        self.heading_links = heading_links;
+    }
+
+    /// HTML inside prepended and appended heading links, `#` by default.
+    #[wasm_bindgen(getter)]
+    pub fn heading_link_content(&self) -> String {
+        self.heading_link_content.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_heading_link_content(&mut self, heading_link_content: String) {
+        self.heading_link_content = heading_link_content;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn heading_link_class(&self) -> String {
+        self.heading_link_class.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_heading_link_class(&mut self, heading_link_class: String) {
+        self.heading_link_class = heading_link_class;
+    }
+
+    /// Lowest heading level in the table of contents, 1 by default.
+    #[wasm_bindgen(getter)]
+    pub fn toc_min_depth(&self) -> u8 {
+        self.toc_min_depth
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_toc_min_depth(&mut self, toc_min_depth: u8) {
+        self.toc_min_depth = toc_min_depth;
+    }
+
+    /// Highest heading level in the table of contents, 6 by default.
+    #[wasm_bindgen(getter)]
+    pub fn toc_max_depth(&self) -> u8 {
+        self.toc_max_depth
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_toc_max_depth(&mut self, toc_max_depth: u8) {
+        self.toc_max_depth = toc_max_depth;
+    }
+
+    /// Return a source map with the rendered code.
+    #[wasm_bindgen(getter)]
+    pub fn source_map(&self) -> bool {
+        self.source_map
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_source_map(&mut self, source_map: bool) {
+        self.source_map = source_map;
+    }
+
+    /// Treat documents as untrusted: no svelte tags, sanitized HTML and only safe URL protocols.
+    #[wasm_bindgen(getter)]
+    pub fn safe(&self) -> bool {
+        self.safe
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_safe(&mut self, safe: bool) {
+        self.safe = safe;
+    }
+
+    /// Layout documents can pick with `layout: name` in frontmatter.
     #[wasm_bindgen]
-    pub fn add_custom_tag(&mut self, tag: String) {
-        self.custom_tags.push(tag);
+    pub fn add_layout(&mut self, name: String, layout: String) {
+        self.layouts.insert(name, layout);
+    }
+
+    /// Named layout for documents whose `path` matches the glob, first added glob wins.
+    #[wasm_bindgen]
+    pub fn add_layout_glob(&mut self, glob: String, name: String) {
+        self.layout_globs.push((glob, name));
+    }
+
+    /// Path of the next rendered document, matched against layout globs.
+    #[wasm_bindgen(getter)]
+    pub fn path(&self) -> String {
+        self.path.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    /// Renders the element as a component: `tag`, `tag=Component` or `tag=Component:source`.
+    #[wasm_bindgen]
+    pub fn add_custom_tag(&mut self, tag: String) -> Result<(), JsError> {
+        self.custom_tags.push(tag.parse().map_err(|err: String| JsError::new(&err))?);
+        Ok(())
+    }
+
+    /// Renders the element as `component`, the default export of `source`, or the layout's
+    /// export named after the element if `source` is missing.
+    #[wasm_bindgen]
+    pub fn add_component(
+        &mut self,
+        tag: String,
+        component: String,
+        source: Option<String>,
+    ) -> Result<(), JsError> {
+        let custom = CustomTag::new(tag, component, source).map_err(|err| JsError::new(&err))?;
+        self.custom_tags.push(custom);
+        Ok(())
+    }
+
+    /// Name of a bundled syntect theme, empty for the default.
+    #[wasm_bindgen(getter)]
+    pub fn theme(&self) -> String {
+        self.theme.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_theme(&mut self, theme: String) {
+        self.theme = theme;
+    }
+
+    /// Emit scope classes instead of inline styles, see `theme_css`.
+    #[wasm_bindgen(getter)]
+    pub fn highlight_classes(&self) -> bool {
+        self.highlight_classes
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_highlight_classes(&mut self, highlight_classes: bool) {
+        self.highlight_classes = highlight_classes;
+    }
+
+    /// `svelte4` (default) or `svelte5`.
+    #[wasm_bindgen(getter)]
+    pub fn target(&self) -> String {
+        self.target.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_target(&mut self, target: String) {
+        self.target = target;
     }
 }
 
+fn highlighter(opts: &Options) -> Result<SyntectHighlighter, String> {
+    let mut highlighter = SyntectHighlighter::new();
+    if !opts.theme.is_empty() {
+        highlighter.set_theme(&opts.theme).map_err(|err| err.to_string())?;
+    }
+    if opts.highlight_classes {
+        highlighter.style = HighlightStyle::Classes;
+    }
+    Ok(highlighter)
+}
+
+/// Stylesheet matching the `highlight_classes` output of the given theme (empty for the default).
+#[wasm_bindgen]
+pub fn theme_css(theme: &str) -> Result<String, JsError> {
+    let mut highlighter = SyntectHighlighter::new();
+    if !theme.is_empty() {
+        highlighter.set_theme(theme).map_err(|err| JsError::new(&err.to_string()))?;
+    }
+    highlighter.css().map_err(|err| JsError::new(&err.to_string()))
+}
+
 #[wasm_bindgen]
 pub fn get_default_options() -> Options {
     Options {
         layout: String::new(),
         custom_tags: Vec::new(),
+        theme: String::new(),
+        highlight_classes: false,
+        target: "svelte4".to_string(),
+        math_fallback: "code".to_string(),
+        heading_links: "none".to_string(),
+        heading_link_content: "#".to_string(),
+        heading_link_class: String::new(),
+        toc_min_depth: 1,
+        toc_max_depth: 6,
+        source_map: false,
+        layouts: HashMap::new(),
+        layout_globs: Vec::new(),
+        path: String::new(),
+        safe: false,
+    }
+}
+
+/// Thrown by `render`. Lines and columns are 1-based, 0 if the position is unknown.
+#[wasm_bindgen]
+pub struct RenderError {
+    message: String,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+#[wasm_bindgen]
+impl RenderError {
+    #[wasm_bindgen(getter)]
+    pub fn message(&self) -> String {
+        self.message.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    #[wasm_bindgen(getter = endLine)]
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+
+    #[wasm_bindgen(getter = endColumn)]
+    pub fn end_column(&self) -> usize {
+        self.end_column
+    }
+}
+
+impl From<MdsvexrsError> for RenderError {
+    fn from(err: MdsvexrsError) -> Self {
+        let pos = err.pos();
+        RenderError {
+            message: err.message(),
+            line: pos.map(|p| p.start.line).unwrap_or_default(),
+            column: pos.map(|p| p.start.column).unwrap_or_default(),
+            end_line: pos.map(|p| p.end.line).unwrap_or_default(),
+            end_column: pos.map(|p| p.end.column).unwrap_or_default(),
+        }
     }
 }
 
+/// Renders a document to `{ code, metadata, titles, toc, script, module_script, style,
+/// warnings, map }`, see `mdsvexrs::RenderOutput`. Positions in `titles` and `warnings` are 1-based.
 #[wasm_bindgen]
-pub fn render(contents: &str, opts: &Options) -> String {
-    Context::new(mdsvexrs::MdsvexrsOptions {
-        layout: opts.layout.to_string(),
-        custom_tags: opts.custom_tags.to_vec(),
-        // path: options.path,
-    })
-    .convert(contents)
+pub fn render(contents: &str, opts: &Options) -> Result<JsValue, RenderError> {
+    let options_error = |message| RenderError {
+        message,
+        line: 0,
+        column: 0,
+        end_line: 0,
+        end_column: 0,
+    };
+    let highlighter = highlighter(opts).map_err(options_error)?;
+    let target = opts.target.parse().map_err(options_error)?;
+    let math_fallback = opts.math_fallback.parse().map_err(options_error)?;
+    let heading_links = opts.heading_links.parse().map_err(options_error)?;
+    let mut ctx = Context::with_highlighter(
+        mdsvexrs::MdsvexrsOptions {
+            layout: opts.layout.to_string(),
+            layouts: opts.layouts.clone(),
+            layout_globs: opts.layout_globs.clone(),
+            custom_tags: opts.custom_tags.clone(),
+            math_fallback,
+            target,
+            heading_links,
+            heading_link_content: opts.heading_link_content.clone(),
+            heading_link_class: opts.heading_link_class.clone(),
+            toc_min_depth: opts.toc_min_depth,
+            toc_max_depth: opts.toc_max_depth,
+            source_map: opts.source_map,
+            path: Some(opts.path.clone()).filter(|path| !path.is_empty()),
+            safe: opts.safe,
+        },
+        Box::new(highlighter),
+    );
+    let output = ctx.render(contents)?;
+    // plain objects instead of `Map`s, so metadata reads like the exported one
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    output
+        .serialize(&serializer)
+        .map_err(|err| options_error(err.to_string()))
 }
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use markdown::{
+    message::{Message, Place},
+    unist::Position,
+};
+use serde::Serialize;
+
+#[derive(Debug)]
+pub enum MdsvexrsError {
+    /// The markdown parser rejected the document.
+    Markdown {
+        message: String,
+        pos: Option<Position>,
+    },
+    /// Frontmatter couldn't be parsed, or isn't a mapping.
+    Frontmatter {
+        message: String,
+        pos: Option<Position>,
+    },
+    /// `defaultLang` in frontmatter is set to something other than a string.
+    DefaultLang { pos: Option<Position> },
+    /// `layout` in frontmatter is neither a layout name nor `false`.
+    InvalidLayout { pos: Option<Position> },
+    /// `layout` in frontmatter names a layout that isn't configured.
+    UnknownLayout { name: String, pos: Option<Position> },
+    /// A layout glob in the options isn't a valid glob.
+    LayoutGlob { glob: String, message: String },
+    /// Custom tags import different components under one name, or one named like a generated
+    /// import.
+    ComponentClash { component: String },
+    /// A `<script` tag without its closing `>`.
+    UnclosedScript { pos: Option<Position> },
+    /// A second instance script, module script or style.
+    DuplicateElement {
+        element: &'static str,
+        pos: Option<Position>,
+    },
+    /// The highlighter failed, e.g. the external highlighter process died.
+    Highlight {
+        message: String,
+        pos: Option<Position>,
+    },
+}
+
+impl MdsvexrsError {
+    /// Where in the markdown source the error happened, if known.
+    pub fn pos(&self) -> Option<&Position> {
+        match self {
+            MdsvexrsError::Markdown { pos, .. }
+            | MdsvexrsError::Frontmatter { pos, .. }
+            | MdsvexrsError::DefaultLang { pos }
+            | MdsvexrsError::InvalidLayout { pos }
+            | MdsvexrsError::UnknownLayout { pos, .. }
+            | MdsvexrsError::UnclosedScript { pos }
+            | MdsvexrsError::DuplicateElement { pos, .. }
+            | MdsvexrsError::Highlight { pos, .. } => pos.as_ref(),
+            MdsvexrsError::LayoutGlob { .. } | MdsvexrsError::ComponentClash { .. } => None,
+        }
+    }
+
+    /// The error description without the position.
+    pub fn message(&self) -> String {
+        match self {
+            MdsvexrsError::Markdown { message, .. } => message.clone(),
+            MdsvexrsError::Frontmatter { message, .. } => format!("invalid frontmatter: {message}"),
+            MdsvexrsError::DefaultLang { .. } => "defaultLang must be a string".to_string(),
+            MdsvexrsError::InvalidLayout { .. } => {
+                "layout must be the name of a layout or false".to_string()
+            }
+            MdsvexrsError::UnknownLayout { name, .. } => format!("unknown layout {name}"),
+            MdsvexrsError::LayoutGlob { glob, message } => {
+                format!("invalid layout glob {glob}: {message}")
+            }
+            MdsvexrsError::ComponentClash { component } => {
+                format!("custom tags import different components as {component}")
+            }
+            MdsvexrsError::UnclosedScript { .. } => {
+                "unclosed script tag (found <script but not >)".to_string()
+            }
+            MdsvexrsError::DuplicateElement { element, .. } => {
+                format!("a document can only have one {element}")
+            }
+            MdsvexrsError::Highlight { message, .. } => format!("highlighting failed: {message}"),
+        }
+    }
+}
+
+impl fmt::Display for MdsvexrsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pos) = self.pos() {
+            write!(f, "{}:{}: ", pos.start.line, pos.start.column)?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for MdsvexrsError {}
+
+/// Something that didn't stop the document from rendering, but likely isn't what was meant.
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub message: String,
+    pub pos: Option<Position>,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(pos) = &self.pos {
+            write!(f, "{}:{}: ", pos.start.line, pos.start.column)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<Message> for MdsvexrsError {
+    fn from(message: Message) -> Self {
+        let pos = message.place.map(|place| match *place {
+            Place::Position(pos) => pos,
+            Place::Point(point) => Position {
+                start: point.clone(),
+                end: point,
+            },
+        });
+        MdsvexrsError::Markdown {
+            message: message.reason,
+            pos,
+        }
+    }
+}
@@ -0,0 +1,376 @@
+//! Code highlighting backends.
+//!
+//! [`SyntectHighlighter`] is the fast in-process default, [`NoHighlighter`] skips highlighting
+//! and [`ExternalHighlighter`] talks to a long-lived process such as `highlighter/index.js`
+//! (shiki) over a line-delimited JSON protocol.
+
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, LazyLock},
+};
+
+use serde::{Deserialize, Serialize};
+use syntect::{
+    dumps::from_uncompressed_data,
+    easy::HighlightLines,
+    highlighting::ThemeSet,
+    html::{
+        append_highlighted_html_for_styled_line, css_for_theme_with_class_style,
+        line_tokens_to_classed_spans, ClassStyle, IncludeBackground,
+    },
+    parsing::{ParseState, ScopeStack, SyntaxSet},
+    util::LinesWithEndings,
+};
+
+pub type HighlightError = Box<dyn Error + Send + Sync>;
+
+/// A piece of code to highlight. Sent as-is (one JSON object per line) to external highlighters.
+#[derive(Debug, Clone, Serialize)]
+pub struct HighlightRequest {
+    pub code: String,
+    pub inline: bool,
+    /// Language token, never empty. `defaultLang` is already applied.
+    pub lang: String,
+    /// Rest of the code fence info string, e.g. `title="main.rs" {1,3}`.
+    pub meta: Option<String>,
+}
+
+pub enum Highlighted {
+    /// Highlighted HTML of each line, without line endings. The renderer wraps every line in a
+    /// `<span data-line>` and the whole block in `<pre><code>` (or just `<code>` when inline).
+    Lines(Vec<String>),
+    /// Complete markup including its own wrapper elements, inserted as-is.
+    Html(String),
+    /// The language isn't known, the code is rendered without highlighting.
+    Unsupported,
+}
+
+pub trait Highlighter {
+    fn highlight(&mut self, request: &HighlightRequest) -> Result<Highlighted, HighlightError>;
+}
+
+pub const DEFAULT_THEME: &str = "base16-ocean.dark";
+
+const CLASS_PREFIX: &str = "hl-";
+
+/// Prefix of the classes emitted by [`HighlightStyle::Classes`], e.g. `hl-keyword`.
+pub const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HighlightStyle {
+    /// Colors from the theme in inline `style` attributes.
+    #[default]
+    Inline,
+    /// Scope classes (see [`CLASS_STYLE`]), colored by a stylesheet from [`SyntectHighlighter::css`].
+    Classes,
+}
+
+/// Bundled syntaxes, deserialized once and shared by every [`SyntectHighlighter`].
+static SYNTAX_SET: LazyLock<Arc<SyntaxSet>> = LazyLock::new(|| {
+    Arc::new(from_uncompressed_data(include_bytes!("../target/syntax_cache.packdump")).unwrap())
+});
+
+static THEME_SET: LazyLock<Arc<ThemeSet>> = LazyLock::new(|| Arc::new(ThemeSet::load_defaults()));
+
+/// Highlights with syntect, using the bundled syntaxes (including the ones in `assets`).
+///
+/// Syntaxes and themes are loaded on first use and shared, so creating a highlighter per
+/// document or per thread is cheap.
+pub struct SyntectHighlighter {
+    pub syntax_set: Arc<SyntaxSet>,
+    /// Copied when [`SyntectHighlighter::load_theme`] adds a theme.
+    pub theme_set: Arc<ThemeSet>,
+    /// Name of the theme in `theme_set` used for inline styles and CSS.
+    pub theme: String,
+    pub style: HighlightStyle,
+}
+
+impl SyntectHighlighter {
+    pub fn new() -> Self {
+        Self {
+            syntax_set: SYNTAX_SET.clone(),
+            theme_set: THEME_SET.clone(),
+            theme: DEFAULT_THEME.to_string(),
+            style: HighlightStyle::Inline,
+        }
+    }
+
+    /// Selects a theme from `theme_set` by name.
+    pub fn set_theme(&mut self, name: &str) -> Result<(), HighlightError> {
+        if !self.theme_set.themes.contains_key(name) {
+            let available = self.theme_set.themes.keys().cloned().collect::<Vec<_>>();
+            let available = available.join(", ");
+            return Err(format!("unknown theme {name}, available: {available}").into());
+        }
+        self.theme = name.to_string();
+        Ok(())
+    }
+
+    /// Loads a `.tmTheme` file and selects it. The theme is named after the file stem.
+    pub fn load_theme(&mut self, path: &Path) -> Result<(), HighlightError> {
+        let theme = ThemeSet::get_theme(path)?;
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // the shared set stays as is, this highlighter gets a copy with the new theme
+        let mut themes = self.theme_set.themes.clone();
+        themes.insert(name.clone(), theme);
+        self.theme_set = Arc::new(ThemeSet { themes });
+        self.theme = name;
+        Ok(())
+    }
+
+    /// Selects a bundled theme by name, or loads it from disk if it's a path to a `.tmTheme`.
+    pub fn use_theme(&mut self, theme: &str) -> Result<(), HighlightError> {
+        if theme.ends_with(".tmTheme") {
+            self.load_theme(Path::new(theme))
+        } else {
+            self.set_theme(theme)
+        }
+    }
+
+    /// Stylesheet for the selected theme, matching [`HighlightStyle::Classes`] output.
+    pub fn css(&self) -> Result<String, HighlightError> {
+        let theme = self
+            .theme_set
+            .themes
+            .get(&self.theme)
+            .ok_or_else(|| format!("unknown theme {}", self.theme))?;
+        Ok(css_for_theme_with_class_style(theme, CLASS_STYLE)?)
+    }
+}
+
+impl Default for SyntectHighlighter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Highlighter for SyntectHighlighter {
+    fn highlight(&mut self, request: &HighlightRequest) -> Result<Highlighted, HighlightError> {
+        let Some(syntax) = self.syntax_set.find_syntax_by_token(&request.lang) else {
+            return Ok(Highlighted::Unsupported);
+        };
+
+        // syntaxes are loaded for newline-terminated lines, inline code has none
+        let code = if request.inline {
+            format!("{}\n", request.code)
+        } else {
+            request.code.clone()
+        };
+        let mut lines = Vec::new();
+
+        if self.style == HighlightStyle::Classes {
+            let mut state = ParseState::new(syntax);
+            let mut stack = ScopeStack::new();
+            for line in LinesWithEndings::from(&code) {
+                // reopen the scopes still open from previous lines, so every line is balanced
+                let mut html: String = stack
+                    .as_slice()
+                    .iter()
+                    .map(|scope| {
+                        let classes = scope
+                            .build_string()
+                            .split('.')
+                            .map(|atom| format!("{CLASS_PREFIX}{atom}"))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        format!("<span class=\"{classes}\">")
+                    })
+                    .collect();
+                let ops = state.parse_line(line, &self.syntax_set)?;
+                html += &line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?.0;
+                html += &"</span>".repeat(stack.len());
+                lines.push(html.replace('\n', ""));
+            }
+            return Ok(Highlighted::Lines(lines));
+        }
+
+        let theme = self
+            .theme_set
+            .themes
+            .get(&self.theme)
+            .ok_or_else(|| format!("unknown theme {}", self.theme))?;
+        let mut highlighter = HighlightLines::new(syntax, theme);
+        for line in LinesWithEndings::from(&code) {
+            let regions = highlighter.highlight_line(line, &self.syntax_set)?;
+            let mut html = String::new();
+            append_highlighted_html_for_styled_line(&regions[..], IncludeBackground::No, &mut html)?;
+            lines.push(html.replace('\n', ""));
+        }
+        Ok(Highlighted::Lines(lines))
+    }
+}
+
+/// Options from a code fence's meta string, in the style of rehype-pretty-code:
+///
+/// ````markdown
+/// ```rs title="main.rs" {1,3-5} showLineNumbers{10}
+/// ````
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeMeta {
+    pub title: Option<String>,
+    pub caption: Option<String>,
+    /// 1-based line ranges (inclusive) to mark with `data-highlighted-line`.
+    pub highlighted_lines: Vec<(usize, usize)>,
+    /// Number of the first line if line numbers are shown.
+    pub line_numbers: Option<i64>,
+}
+
+impl CodeMeta {
+    pub fn parse(meta: &str) -> Self {
+        let mut result = CodeMeta::default();
+        let mut rest = meta.trim_start();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("title=") {
+                let (value, after) = quoted(after);
+                result.title = Some(value.to_string());
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("caption=") {
+                let (value, after) = quoted(after);
+                result.caption = Some(value.to_string());
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("showLineNumbers") {
+                let (start, after) = match after.strip_prefix('{').and_then(|a| a.split_once('}')) {
+                    Some((start, after)) => (start.trim().parse().unwrap_or(1), after),
+                    None => (1, after),
+                };
+                result.line_numbers = Some(start);
+                rest = after;
+            } else if let Some((ranges, after)) =
+                rest.strip_prefix('{').and_then(|a| a.split_once('}'))
+            {
+                result.highlighted_lines.extend(ranges.split(',').filter_map(|range| {
+                    let (start, end) = range.split_once('-').unwrap_or((range, range));
+                    Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+                }));
+                rest = after;
+            } else {
+                // unknown word, e.g. meta meant for another tool
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                rest = &rest[end..];
+            }
+            rest = rest.trim_start();
+        }
+        result
+    }
+
+    pub fn is_highlighted(&self, line: usize) -> bool {
+        self.highlighted_lines
+            .iter()
+            .any(|&(start, end)| start <= line && line <= end)
+    }
+}
+
+/// Splits a `"quoted"` (or `'quoted'`) value off the start of `s`, or a bare word if unquoted.
+fn quoted(s: &str) -> (&str, &str) {
+    if let Some(quote @ ('"' | '\'')) = s.chars().next() {
+        if let Some(end) = s[1..].find(quote) {
+            return (&s[1..end + 1], &s[end + 2..]);
+        }
+    }
+    let end = s.find(char::is_whitespace).unwrap_or(s.len());
+    (&s[..end], &s[end..])
+}
+
+/// Leaves all code unhighlighted.
+pub struct NoHighlighter;
+
+impl Highlighter for NoHighlighter {
+    fn highlight(&mut self, _request: &HighlightRequest) -> Result<Highlighted, HighlightError> {
+        Ok(Highlighted::Unsupported)
+    }
+}
+
+#[derive(Deserialize)]
+struct HighlightResponse {
+    html: String,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// Sends requests to an external process, one JSON [`HighlightRequest`] per line on its stdin,
+/// and reads one `{"html": "…"}` line per request from its stdout.
+///
+/// The process is spawned on first use and reused for every following request.
+pub struct ExternalHighlighter {
+    command: Command,
+    process: Option<Process>,
+}
+
+impl ExternalHighlighter {
+    pub fn new(command: Command) -> Self {
+        Self {
+            command,
+            process: None,
+        }
+    }
+
+    fn process(&mut self) -> Result<&mut Process, HighlightError> {
+        if self.process.is_none() {
+            let mut child = self
+                .command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .spawn()?;
+            let stdin = child.stdin.take().ok_or("highlighter stdin is not available")?;
+            let stdout = child.stdout.take().ok_or("highlighter stdout is not available")?;
+            self.process = Some(Process {
+                child,
+                stdin,
+                stdout: BufReader::new(stdout),
+            });
+        }
+        Ok(self.process.as_mut().unwrap())
+    }
+}
+
+impl Highlighter for ExternalHighlighter {
+    fn highlight(&mut self, request: &HighlightRequest) -> Result<Highlighted, HighlightError> {
+        let process = self.process()?;
+        let result = (|| -> Result<Highlighted, HighlightError> {
+            let mut line = serde_json::to_string(request)?;
+            line.push('\n');
+            process.stdin.write_all(line.as_bytes())?;
+            process.stdin.flush()?;
+
+            let mut response = String::new();
+            if process.stdout.read_line(&mut response)? == 0 {
+                return Err("highlighter process exited".into());
+            }
+            let response: HighlightResponse = serde_json::from_str(&response)?;
+            Ok(Highlighted::Html(response.html))
+        })();
+        if result.is_err() {
+            // the protocol is out of sync or the process died, start over on the next request
+            if let Some(mut process) = self.process.take() {
+                let _ = process.child.kill();
+                let _ = process.child.wait();
+            }
+        }
+        result
+    }
+}
+
+impl Drop for ExternalHighlighter {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.take() {
+            // closing stdin lets the process finish on its own
+            let Process {
+                mut child, stdin, ..
+            } = process;
+            drop(stdin);
+            let _ = child.wait();
+        }
+    }
+}
@@ -1,25 +1,36 @@
 use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
     sync::LazyLock,
-    time::{Duration/*, Instant*/},
+    // time::{Duration, Instant},
 };
 
 use itertools::Itertools;
 use markdown::{
     mdast::{
-        Blockquote, Break, Code, Definition, Delete, Emphasis, FootnoteDefinition,
+        AlignKind, Blockquote, Break, Code, Definition, Delete, Emphasis, FootnoteDefinition,
         FootnoteReference, Heading, Html, Image, ImageReference, InlineCode, InlineMath, Link,
         LinkReference, List, ListItem, Math, MdxFlowExpression, MdxJsxFlowElement,
-        MdxJsxTextElement, MdxTextExpression, MdxjsEsm, Node, Paragraph, Root, Strong, Table,
-        TableCell, TableRow, Text, ThematicBreak, Toml, Yaml,
+        MdxJsxTextElement, MdxTextExpression, MdxjsEsm, Node, Paragraph, ReferenceKind, Root,
+        Strong, Table, TableCell, TableRow, Text, ThematicBreak, Toml, Yaml,
     },
-    unist::Position,
+    unist::{Point, Position},
     Constructs,
 };
 use serde::Serialize;
 use serde_json::Value;
-use syntect::{
-    dumps::from_uncompressed_data, easy::HighlightLines, highlighting::ThemeSet, html::{append_highlighted_html_for_styled_line, IncludeBackground}, parsing::{SyntaxDefinition, SyntaxSet}, util::LinesWithEndings
-};
+
+mod error;
+pub mod highlight;
+pub mod math;
+mod mustache;
+pub mod sanitize;
+pub mod sourcemap;
+
+pub use error::{MdsvexrsError, Warning};
+use highlight::{CodeMeta, HighlightRequest, Highlighted, Highlighter, SyntectHighlighter};
+use mustache::{Segment, Tags};
+use sourcemap::SourceMap;
 
 #[derive(Debug)]
 struct ToHtmlResult {
@@ -47,7 +58,9 @@ impl ToHtmlResult {
 trait ToHtml {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult;
 
-    fn visit(&self, _ctx: &mut Context) {}
+    fn visit(&self, _ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        Ok(())
+    }
 }
 
 impl<T> ToHtml for Vec<T>
@@ -58,10 +71,11 @@ where
         merge(&self.iter().map(|t| t.to_html(ctx)).collect::<Vec<_>>())
     }
 
-    fn visit(&self, ctx: &mut Context) {
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
         for t in self {
-            t.visit(ctx);
+            t.visit(ctx)?;
         }
+        Ok(())
     }
 }
 
@@ -70,8 +84,8 @@ impl ToHtml for Root {
         self.children.to_html(ctx)
     }
 
-    fn visit(&self, ctx: &mut Context) {
-        self.children.visit(ctx);
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        self.children.visit(ctx)
     }
 }
 
@@ -83,15 +97,24 @@ impl ToHtml for Blockquote {
             children.svelte,
         )
     }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        self.children.visit(ctx)
+    }
 }
 
 impl ToHtml for FootnoteDefinition {
     fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+        // rendered in the footnote section at the end of the document
+        ToHtmlResult::empty()
     }
 
-    fn visit(&self, _ctx: &mut Context) {
-        todo!()
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        // first definition wins, like GitHub
+        ctx.footnote_definitions
+            .entry(self.identifier.clone())
+            .or_insert_with(|| self.clone());
+        self.children.visit(ctx)
     }
 }
 
@@ -103,17 +126,40 @@ impl ToHtml for MdxJsxFlowElement {
 
 impl ToHtml for List {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        // todo!()
         let litype = match self.ordered {
             true => "ol",
             false => "ul",
         };
+        let mut attrs = match self.start {
+            Some(start) if self.ordered && start != 1 => format!("start=\"{start}\""),
+            _ => String::new(),
+        };
+        let tasks = self
+            .children
+            .iter()
+            .any(|item| matches!(item, Node::ListItem(item) if item.checked.is_some()));
+        if tasks {
+            attrs += if attrs.is_empty() { "" } else { " " };
+            attrs += "class=\"contains-task-list\"";
+        }
+        // like GitHub, a list is loose if any of its items is
+        let tight = !self.spread
+            && !self
+                .children
+                .iter()
+                .any(|item| matches!(item, Node::ListItem(item) if item.spread));
+        let outer = std::mem::replace(&mut ctx.tight_list, tight);
         let children = self.children.to_html(ctx);
+        ctx.tight_list = outer;
         ToHtmlResult::from_wrapped(
-            ctx.wrap_in_tag(litype, "", children.html),
+            ctx.wrap_in_tag(litype, &attrs, children.html),
             children.svelte,
         )
     }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        self.children.visit(ctx)
+    }
 }
 
 impl ToHtml for MdxjsEsm {
@@ -124,7 +170,75 @@ impl ToHtml for MdxjsEsm {
 
 impl ToHtml for Toml {
     fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        unimplemented!()
+        ToHtmlResult::empty()
+    }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        let table: toml::Table =
+            toml::from_str(&self.value).map_err(|err| MdsvexrsError::Frontmatter {
+                pos: match err.span() {
+                    Some(span) => frontmatter_pos(&self.value, span.start, self.position.as_ref()),
+                    None => self.position.clone(),
+                },
+                message: match err.message().lines().join(", ") {
+                    message if message.is_empty() => "invalid TOML".to_string(),
+                    message => message,
+                },
+            })?;
+        let value = table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect();
+        ctx.set_frontmatter(value, self.position.clone())
+    }
+}
+
+/// Splits a Hugo-style JSON object frontmatter off the start of the document.
+///
+/// Returns the parsed object and the length of the source it spans. Only a document starting
+/// with `{` alone on its first line is treated as JSON frontmatter, anything else is content.
+fn json_frontmatter(input: &str) -> Result<Option<(Frontmatter, usize)>, MdsvexrsError> {
+    let first_line = input.lines().next().unwrap_or_default();
+    if first_line.trim_end() != "{" {
+        return Ok(None);
+    }
+    let mut stream = serde_json::Deserializer::from_str(input).into_iter::<Frontmatter>();
+    match stream.next() {
+        Some(Ok(value)) => {
+            let end = stream.byte_offset();
+            // like other frontmatter, the object has to end its line
+            let rest = &input[end..];
+            let line_end = rest.find('\n').unwrap_or(rest.len());
+            if !rest[..line_end].trim().is_empty() {
+                let point = point_at(input, end);
+                return Err(MdsvexrsError::Frontmatter {
+                    message: "expected a line ending after JSON frontmatter".to_string(),
+                    pos: Some(Position {
+                        start: point.clone(),
+                        end: point,
+                    }),
+                });
+            }
+            Ok(Some((value, end + line_end)))
+        }
+        Some(Err(err)) => {
+            // serde_json reports a line and a byte column (0 at the end of input), but no offset
+            let line_start = input
+                .split_inclusive('\n')
+                .take(err.line().saturating_sub(1))
+                .map(str::len)
+                .sum::<usize>();
+            let mut offset = (line_start + err.column().saturating_sub(1)).min(input.len());
+            while !input.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            let point = point_at(input, offset);
+            Err(MdsvexrsError::Frontmatter {
+                message: err.to_string().split(" at line ").next().unwrap_or_default().to_string(),
+                pos: Some(Position {
+                    start: point.clone(),
+                    end: point,
+                }),
+            })
+        }
+        None => Ok(None),
     }
 }
 
@@ -133,9 +247,74 @@ impl ToHtml for Yaml {
         ToHtmlResult::empty()
     }
 
-    fn visit(&self, ctx: &mut Context) {
-        let value = serde_yaml::from_str(&self.value).unwrap();
-        ctx.yaml = Some(value);
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        let value = if self.value.trim().is_empty() {
+            serde_json::Map::new()
+        } else {
+            serde_yaml::from_str(&self.value).map_err(|err| MdsvexrsError::Frontmatter {
+                pos: match err.location() {
+                    Some(location) => {
+                        frontmatter_pos(&self.value, location.index(), self.position.as_ref())
+                    }
+                    None => self.position.clone(),
+                },
+                // the location is reported in `pos`, relative to the whole document
+                message: err
+                    .to_string()
+                    .split(" at line ")
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            })?
+        };
+        ctx.set_frontmatter(value, self.position.clone())
+    }
+}
+
+/// Translates a byte index into frontmatter `value` into a position in the markdown document.
+fn frontmatter_pos(value: &str, index: usize, node: Option<&Position>) -> Option<Position> {
+    let node = node?;
+    let inner = point_at(value, index.min(value.len()));
+    // content starts on the line after the opening fence
+    let fence = if value.contains("\r\n") { 5 } else { 4 };
+    let point = Point::new(
+        node.start.line + inner.line,
+        inner.column,
+        node.start.offset + fence + inner.offset,
+    );
+    Some(Position {
+        start: point.clone(),
+        end: point,
+    })
+}
+
+/// Spaces in place of `text`, keeping line endings and the length in bytes.
+fn blank(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\n' | '\r' => c.to_string(),
+            c => " ".repeat(c.len_utf8()),
+        })
+        .collect()
+}
+
+/// Line and column of a byte offset in `source`.
+fn point_at(source: &str, offset: usize) -> Point {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    Point::new(line, column, offset)
+}
+
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(a) => Value::Array(a.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(t) => Value::Object(t.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
     }
 }
 
@@ -151,40 +330,48 @@ static LANG_HINT_REGEX: LazyLock<regex::Regex> =
 
 impl ToHtml for InlineCode {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let value = &self.value;
+        let value = &ctx.restore(&self.value, self.position.as_ref());
         // if value ends with {lang} then it's a language hint
         let output = if let Some(caps) = LANG_HINT_REGEX.captures(value) {
             let lang = &caps["lang"];
             let code = &value[..value.len() - lang.len() - 3];
-            ctx.highlight(HighlightRequest {
-                lang: lang.to_string(),
-                inline: true,
-                code: code.to_string(),
-                meta: None,
-            })
+            ctx.highlight(
+                HighlightRequest {
+                    lang: lang.to_string(),
+                    inline: true,
+                    code: code.to_string(),
+                    meta: None,
+                },
+                self.position.as_ref(),
+            )
         } else if let Some(lang) = &ctx.default_lang {
-            ctx.highlight(HighlightRequest {
-                lang: lang.clone(),
-                inline: true,
-                code: value.clone(),
-                meta: None,
-            })
+            ctx.highlight(
+                HighlightRequest {
+                    lang: lang.clone(),
+                    inline: true,
+                    code: value.clone(),
+                    meta: None,
+                },
+                self.position.as_ref(),
+            )
         } else {
-            ctx.wrap_in_tag("code", "", html_encode(&self.value))
+            ctx.wrap_in_tag("code", "", text_encode(value))
         };
         ToHtmlResult::from_wrapped(output, false)
     }
 }
 
 impl ToHtml for InlineMath {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        ToHtmlResult::new(self.value.clone(), false)
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let tex = ctx.restore(&self.value, self.position.as_ref());
+        ToHtmlResult::from_wrapped(ctx.math(&tex, false, self.position.as_ref()), false)
     }
 }
 
 impl ToHtml for Delete {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let children = self.children.to_html(ctx);
+        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("del", "", children.html), children.svelte)
     }
 }
 
@@ -202,43 +389,146 @@ impl ToHtml for MdxTextExpression {
 }
 
 impl ToHtml for FootnoteReference {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let Some(definition) = ctx.footnote_definitions.get(&self.identifier) else {
+            // undefined footnotes are left as written
+            let label = self.label.as_ref().unwrap_or(&self.identifier);
+            return ToHtmlResult::new(text_encode(&format!("[^{label}]")), false);
+        };
+        let pos = definition.position.clone();
+        let footnote = match ctx.footnotes.iter().position(|f| f.label == self.identifier) {
+            Some(i) => &mut ctx.footnotes[i],
+            None => {
+                let index = ctx.footnotes.len() + 1;
+                ctx.footnotes.push(Footnote {
+                    index,
+                    label: self.identifier.clone(),
+                    id: footnote_id(index),
+                    references: 0,
+                    pos,
+                });
+                ctx.footnotes.last_mut().unwrap()
+            }
+        };
+        footnote.references += 1;
+        let index = footnote.index;
+        let ref_id = footnote_ref_id(index, footnote.references);
+        let link = ctx.wrap_in_tag(
+            "a",
+            &format!(
+                "href=\"#{}\" id=\"{ref_id}\" data-footnote-ref aria-describedby=\"{FOOTNOTE_LABEL_ID}\"",
+                footnote_id(index)
+            ),
+            index.to_string(),
+        );
+        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("sup", "", link), false)
     }
 }
 
 impl ToHtml for Html {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let value = self.value.clone();
-        if value.starts_with("<script") {
-            ctx.script = Some(value);
-
-            return ToHtmlResult::empty();
+        // html is passed to svelte as is, so tags in attributes and the like keep working
+        let value = ctx.restore(&self.value, self.position.as_ref());
+        if value.trim() == "<!-- toc -->" {
+            return ctx.toc_html();
+        }
+        if ctx.options.safe {
+            return ToHtmlResult::new(sanitize::html(&value), false);
         }
         ToHtmlResult::new(value, true)
     }
 }
 
+/// Encoded `src` and `title` of an image or `href` and `title` of a link, each with whether it
+/// contains svelte tags.
+struct LinkAttributes {
+    url: (String, bool),
+    title: Option<(String, bool)>,
+}
+
+impl LinkAttributes {
+    fn new(url: Vec<Segment>, title: Vec<Segment>, has_title: bool) -> Self {
+        LinkAttributes {
+            url: attribute(url, url_encode),
+            title: has_title.then(|| attribute(title, text_encode)),
+        }
+    }
+
+    /// Attributes of the definition a reference points to.
+    fn of_definition(ctx: &Context, definition: &Definition) -> Self {
+        let [_, url, title] = ctx.split_all(
+            [
+                definition.label.as_deref().unwrap_or_default(),
+                &definition.url,
+                definition.title.as_deref().unwrap_or_default(),
+            ],
+            definition.position.as_ref(),
+        );
+        LinkAttributes::new(url, title, definition.title.is_some())
+    }
+
+    /// The title attribute with a leading space, if there is a title.
+    fn title_html(&self) -> String {
+        self.title
+            .as_ref()
+            .map(|(title, _)| format!(" title=\"{title}\""))
+            .unwrap_or_default()
+    }
+
+    fn svelte(&self) -> bool {
+        self.url.1 || self.title.as_ref().is_some_and(|(_, svelte)| *svelte)
+    }
+
+    /// Empties the url in safe mode if its protocol isn't one of `protocols`.
+    fn restrict(mut self, ctx: &Context, protocols: &[&str]) -> Self {
+        if ctx.options.safe && !sanitize::url_allowed(&self.url.0, protocols) {
+            self.url = (String::new(), false);
+        }
+        self
+    }
+}
+
+fn image_html(ctx: &Context, attributes: LinkAttributes, alt: (String, bool)) -> ToHtmlResult {
+    let (tag, changed) = ctx.resolve_tag("img");
+    let attributes = attributes.restrict(ctx, sanitize::SAFE_SRC_PROTOCOLS);
+    let (url, title) = (&attributes.url.0, attributes.title_html());
+    ToHtmlResult::new(
+        // self-closed like all void elements, components can't be left open
+        format!("<{tag} src=\"{url}\" alt=\"{}\"{title} />", alt.0),
+        changed || attributes.svelte() || alt.1,
+    )
+}
+
 impl ToHtml for Image {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let alt = &self.alt;
-        let title = self
-            .title
-            .as_ref()
-            .map(|t| format!(" title=\"{}\"", t))
-            .unwrap_or_default();
-        let url = &self.url;
-        let (tag, changed) = ctx.resolve_tag("img");
-        ToHtmlResult::new(
-            format!("<{tag} src=\"{url}\" alt=\"{alt}\"{title}>"),
-            changed,
-        )
+        let title = self.title.as_deref().unwrap_or_default();
+        let [alt, url, title] =
+            ctx.split_all([&self.alt, &self.url, title], self.position.as_ref());
+        let attributes = LinkAttributes::new(url, title, self.title.is_some());
+        image_html(ctx, attributes, attribute(alt, text_encode))
+    }
+}
+
+/// Source text of an unresolved reference, e.g. `[label]` for `[text][label]`.
+fn reference_suffix(kind: &ReferenceKind, identifier: &str, label: Option<&String>) -> String {
+    match kind {
+        ReferenceKind::Shortcut => String::new(),
+        ReferenceKind::Collapsed => "[]".to_string(),
+        ReferenceKind::Full => format!("[{}]", label.map(String::as_str).unwrap_or(identifier)),
     }
 }
 
 impl ToHtml for ImageReference {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let Some(definition) = ctx.definitions.get(&self.identifier) else {
+            let suffix =
+                reference_suffix(&self.reference_kind, &self.identifier, self.label.as_ref());
+            let alt = ctx.restore(&self.alt, self.position.as_ref());
+            return ToHtmlResult::new(text_encode(&format!("![{alt}]{suffix}")), false);
+        };
+        let attributes = LinkAttributes::of_definition(ctx, definition);
+        let [alt] = ctx.split_all([&self.alt], self.position.as_ref());
+        image_html(ctx, attributes, attribute(alt, text_encode))
     }
 }
 
@@ -248,24 +538,52 @@ impl ToHtml for MdxJsxTextElement {
     }
 }
 
+fn link_html(ctx: &Context, attributes: LinkAttributes, children: ToHtmlResult) -> ToHtmlResult {
+    let attributes = attributes.restrict(ctx, sanitize::SAFE_HREF_PROTOCOLS);
+    let attrs = format!("href=\"{}\"{}", attributes.url.0, attributes.title_html());
+    ToHtmlResult::from_wrapped(
+        ctx.wrap_in_tag("a", &attrs, children.html),
+        children.svelte || attributes.svelte(),
+    )
+}
+
 impl ToHtml for Link {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
         let children = self.children.to_html(ctx);
-        let title = self
-            .title
-            .as_ref()
-            .map(|t| format!(" title=\"{}\"", t))
-            .unwrap_or_default();
-        ToHtmlResult::from_wrapped(
-            ctx.wrap_in_tag("a", &format!("href=\"{}\"{}", self.url, title), children.html),
-            children.svelte,
-        )
+        // the destination and title come after the text, which restores its own tags, except
+        // in autolinks where both are the same source
+        let autolink = matches!(
+            self.children.as_slice(),
+            [Node::Text(text)] if self.url.ends_with(&text.value)
+        );
+        let pos = self.position.as_ref().map(|pos| Position {
+            start: match self.children.last().and_then(Node::position) {
+                Some(last) if !autolink => last.end.clone(),
+                _ => pos.start.clone(),
+            },
+            end: pos.end.clone(),
+        });
+        let title = self.title.as_deref().unwrap_or_default();
+        let [url, title] = ctx.split_all([&self.url, title], pos.as_ref());
+        let attributes = LinkAttributes::new(url, title, self.title.is_some());
+        link_html(ctx, attributes, children)
     }
 }
 
 impl ToHtml for LinkReference {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let children = self.children.to_html(ctx);
+        let Some(definition) = ctx.definitions.get(&self.identifier) else {
+            let suffix =
+                reference_suffix(&self.reference_kind, &self.identifier, self.label.as_ref());
+            return merge(&[
+                ToHtmlResult::new("[".to_string(), false),
+                children,
+                ToHtmlResult::new(format!("]{}", text_encode(&suffix)), false),
+            ]);
+        };
+        let attributes = LinkAttributes::of_definition(ctx, definition);
+        link_html(ctx, attributes, children)
     }
 }
 
@@ -280,32 +598,55 @@ impl ToHtml for Strong {
 }
 
 impl ToHtml for Text {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        ToHtmlResult::new(html_encode(&self.value), false)
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let segments = ctx.tags.split(&[&self.value], self.position.as_ref()).remove(0);
+        let results = segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => ToHtmlResult::new(text_encode(&text), false),
+                Segment::Tag(tag) => ToHtmlResult::new(tag, true),
+            })
+            .collect::<Vec<_>>();
+        merge(&results)
     }
 }
 
 impl ToHtml for Code {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let value = &self.value;
-        let lang = self.lang.as_ref().or(ctx.default_lang.as_ref());
+        let [lang, meta, value] = ctx.restore_all(
+            [
+                self.lang.as_deref().unwrap_or_default(),
+                self.meta.as_deref().unwrap_or_default(),
+                &self.value,
+            ],
+            self.position.as_ref(),
+        );
+        let value = &value;
+        let meta = self.meta.as_ref().map(|_| meta);
+        let lang = self.lang.as_ref().map(|_| lang);
+        let lang = lang.as_ref().or(ctx.default_lang.as_ref());
         let highlighted = if let Some(lang) = lang {
-            ctx.highlight(HighlightRequest {
-                code: value.clone(),
-                inline: false,
-                lang: lang.clone(),
-                meta: self.meta.clone(),
-            })
+            ctx.highlight(
+                HighlightRequest {
+                    code: value.clone(),
+                    inline: false,
+                    lang: lang.clone(),
+                    meta,
+                },
+                self.position.as_ref(),
+            )
         } else {
-            ctx.wrap_in_tag("pre", "", ctx.wrap_in_tag("code", "", html_encode(&self.value)))
+            ctx.wrap_in_tag("pre", "", ctx.wrap_in_tag("code", "", text_encode(value)))
         };
         ToHtmlResult::from_wrapped(highlighted, false)
     }
 }
 
 impl ToHtml for Math {
-    fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+    fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        let tex = ctx.restore(&self.value, self.position.as_ref());
+        let (html, svelte) = ctx.math(&tex, true, self.position.as_ref());
+        ToHtmlResult::new(format!("{html}\n"), svelte)
     }
 }
 
@@ -315,57 +656,179 @@ impl ToHtml for MdxFlowExpression {
     }
 }
 
+static SLUG_REMOVE_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"[^\p{L}\p{M}\p{N}\p{Pc} -]").unwrap());
+
+/// Slug of a heading's text, like github-slugger: lowercase, without punctuation or emoji, with
+/// spaces turned into dashes.
+///
+/// Footnote ids contain `:`, so slugs can never clash with them, and custom ids in their
+/// namespace are replaced by the slug (see [`is_footnote_id`]).
 fn slug(str: &str) -> String {
-    str.to_lowercase().replace(" ", "-")
+    SLUG_REMOVE_REGEX
+        .replace_all(&str.to_lowercase(), "")
+        .replace(' ', "-")
+}
+
+/// Custom heading id written after the heading text, e.g. `## Usage {#usage}`.
+static HEADING_ID_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"^\{#(?<id>[^\s{}]+)\}$").unwrap());
+
+/// Text content of phrasing nodes, as rendered without markup.
+fn text_content(ctx: &Context, nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Text(t) => ctx.restore(&t.value, t.position.as_ref()),
+            Node::InlineCode(c) => ctx.restore(&c.value, c.position.as_ref()),
+            Node::InlineMath(m) => ctx.restore(&m.value, m.position.as_ref()),
+            Node::Image(i) => ctx.restore(&i.alt, i.position.as_ref()),
+            Node::ImageReference(i) => ctx.restore(&i.alt, i.position.as_ref()),
+            Node::Break(_) => " ".to_string(),
+            node => node.children().map(|c| text_content(ctx, c)).unwrap_or_default(),
+        })
+        .collect()
+}
+
+const FOOTNOTE_LABEL_ID: &str = "fn:label";
+
+fn footnote_id(index: usize) -> String {
+    format!("fn:{index}")
+}
+
+/// Whether `id` is in the namespace of the footnote ids above.
+fn is_footnote_id(id: &str) -> bool {
+    id.starts_with("fn:") || id.starts_with("fnref:")
+}
+
+fn footnote_ref_id(index: usize, reference: usize) -> String {
+    if reference == 1 {
+        format!("fnref:{index}")
+    } else {
+        format!("fnref:{index}:{reference}")
+    }
+}
+
+/// Children of a heading without a trailing `{#id}`, and that id.
+fn split_heading_id(ctx: &Context, heading: &Heading) -> (Vec<Node>, Option<String>) {
+    let mut nodes = heading.children.clone();
+    let custom_id = match nodes.last_mut() {
+        Some(Node::Text(text)) => ctx.heading_id(text),
+        _ => None,
+    };
+    (nodes, custom_id)
 }
 
 impl ToHtml for Heading {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let children = self.children.to_html(ctx);
-        let text = self
-            .children
+        let (nodes, _) = split_heading_id(ctx, self);
+        let children = nodes.to_html(ctx);
+        let offset = self.position.as_ref().map(|pos| pos.start.offset);
+        let slug = ctx
+            .titles
             .iter()
-            .filter_map(|c| match c {
-                Node::Text(t) => Some(t.value.clone()),
-                _ => None,
-            })
-            .join("");
-        let mut slug = slug(&text);
-        if ctx.titles.iter().any(|t| t.id == slug) {
-            let mut i = 1;
-            while ctx.titles.iter().any(|t| t.id == format!("{}-{}", slug, i)) {
-                i += 1;
-            }
-            slug = format!("{}-{}", slug, i);
-        }
-        ctx.titles.push(Title {
-            level: self.depth,
-            text: text.clone(),
-            id: slug.clone(),
-            pos: self.position.clone(),
-        });
+            .find(|title| title.pos.as_ref().map(|pos| pos.start.offset) == offset)
+            .map(|title| text_encode(&title.id))
+            .unwrap_or_default();
         let (tag, changed) = ctx.resolve_tag(&format!("h{}", self.depth));
+        // components get the level as a prop, elements have it in their name
+        let depth = if changed {
+            format!(" depth={{{}}}", self.depth)
+        } else {
+            String::new()
+        };
+        let children = ctx.heading_link(children, &slug);
         ToHtmlResult::new(
             format!(
-                "\n<{tag} id=\"{slug}\">{}</{tag}>\n",
+                "\n<{tag} id=\"{slug}\"{depth}>{}</{tag}>\n",
                 children.html
             ),
             children.svelte || changed,
         )
     }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        // titles are collected up front so a table of contents can come before the headings
+        let (nodes, custom_id) = split_heading_id(ctx, self);
+        let text = text_content(ctx, &nodes);
+        let id = match custom_id {
+            Some(id) if is_footnote_id(&id) => {
+                let message = format!("heading id {id} is reserved for footnotes, using the slug");
+                ctx.warn(message, self.position.as_ref());
+                ctx.unique_slug(slug(text.trim()))
+            }
+            Some(id) => {
+                if ctx.slugs.insert(id.clone(), 0).is_some() {
+                    ctx.warn(format!("heading id {id} is used twice"), self.position.as_ref());
+                }
+                id
+            }
+            None => ctx.unique_slug(slug(text.trim())),
+        };
+        ctx.titles.push(Title {
+            level: self.depth,
+            text: text.trim().to_string(),
+            id,
+            pos: self.position.clone(),
+        });
+        Ok(())
+    }
 }
 
 impl ToHtml for Table {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let children = self.children.to_html(ctx);
-        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("table", "", children.html), children.svelte)
+        // the first row is the header, like in GFM
+        let mut rows = self.children.iter().filter_map(|row| match row {
+            Node::TableRow(row) => Some(row),
+            _ => None,
+        });
+        let mut sections = Vec::new();
+        if let Some(head) = rows.next() {
+            let head = table_row(ctx, head, "th", &self.align);
+            sections.push(ToHtmlResult::from_wrapped(ctx.wrap_in_tag("thead", "", head), false));
+        }
+        let body = rows
+            .map(|row| table_row(ctx, row, "td", &self.align))
+            .collect::<Vec<_>>();
+        if !body.is_empty() {
+            let body = merge(&body);
+            sections.push(ToHtmlResult::from_wrapped(ctx.wrap_in_tag("tbody", "", body), false));
+        }
+        let content = merge(&sections);
+        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("table", "", content), false)
     }
 }
 
+/// Renders a table row with `cell` elements, aligned per column.
+///
+/// Like GFM, rows have as many cells as the table has columns: missing ones are added empty and
+/// extra ones are dropped.
+fn table_row(ctx: &mut Context, row: &TableRow, cell: &str, align: &[AlignKind]) -> ToHtmlResult {
+    let cells = align
+        .iter()
+        .enumerate()
+        .map(|(i, align)| {
+            let children = match row.children.get(i) {
+                Some(Node::TableCell(c)) => c.children.to_html(ctx),
+                Some(node) => node.to_html(ctx),
+                None => ToHtmlResult::empty(),
+            };
+            let attrs = match align {
+                AlignKind::Left => "align=\"left\"",
+                AlignKind::Right => "align=\"right\"",
+                AlignKind::Center => "align=\"center\"",
+                AlignKind::None => "",
+            };
+            ToHtmlResult::from_wrapped(ctx.wrap_in_tag(cell, attrs, children), false)
+        })
+        .collect::<Vec<_>>();
+    ToHtmlResult::from_wrapped(ctx.wrap_in_tag("tr", "", merge(&cells)), false)
+}
+
 impl ToHtml for ThematicBreak {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
         let (tag, changed) = ctx.resolve_tag("hr");
-        ToHtmlResult::new(format!("\n<{tag}>\n"), changed)
+        ToHtmlResult::new(format!("\n<{tag} />\n"), changed)
     }
 }
 
@@ -385,19 +848,94 @@ impl ToHtml for TableCell {
 
 impl ToHtml for ListItem {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-        let children = self.children.to_html(ctx);
-        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("li", "", children.html), children.svelte)
+        let checkbox = self.checked.map(|checked| {
+            let (tag, changed) = ctx.resolve_tag("input");
+            let checked = if checked { " checked" } else { "" };
+            ToHtmlResult::new(format!("<{tag} type=\"checkbox\" disabled{checked} /> "), changed)
+        });
+        let tight = ctx.tight_list;
+        let mut results = Vec::new();
+        for (i, child) in self.children.iter().enumerate() {
+            let checkbox = if i == 0 { checkbox.as_ref() } else { None };
+            match child {
+                // paragraphs of tight lists are unwrapped, and the checkbox goes into the first
+                Node::Paragraph(paragraph) if tight || checkbox.is_some() => {
+                    let mut content = Vec::new();
+                    if let Some(checkbox) = checkbox {
+                        content.push(ToHtmlResult::new(checkbox.html.clone(), checkbox.svelte));
+                    }
+                    content.push(paragraph.children.to_html(ctx));
+                    let content = merge(&content);
+                    if tight {
+                        results.push(content);
+                    } else {
+                        results.push(ToHtmlResult::from_wrapped(
+                            ctx.wrap_in_tag("p", "", content),
+                            false,
+                        ));
+                    }
+                }
+                child => {
+                    if let Some(checkbox) = checkbox {
+                        results.push(ToHtmlResult::new(checkbox.html.clone(), checkbox.svelte));
+                    }
+                    results.push(child.to_html(ctx));
+                }
+            }
+        }
+        let attrs = match self.checked {
+            Some(_) => "class=\"task-list-item\"",
+            None => "",
+        };
+        let children = merge(&results);
+        ToHtmlResult::from_wrapped(ctx.wrap_in_tag("li", attrs, children.html), children.svelte)
+    }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        self.children.visit(ctx)
     }
 }
 
 impl ToHtml for Definition {
     fn to_html(&self, _ctx: &mut Context) -> ToHtmlResult {
-        todo!()
+        ToHtmlResult::empty()
+    }
+
+    fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
+        // identifiers are already normalized (whitespace collapsed, case-folded) by the parser,
+        // first definition wins per CommonMark
+        // tags in the url and title are restored by the references, from the position
+        if !ctx.definitions.contains_key(&self.identifier) {
+            ctx.definitions.insert(self.identifier.clone(), self.clone());
+        }
+        Ok(())
     }
 }
 
 impl ToHtml for Paragraph {
     fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
+        if text_content(ctx, &self.children).trim() == "[[toc]]" {
+            return ctx.toc_html();
+        }
+        // block tags on their own (e.g. `{#if x}` followed by a blank line) can open in one
+        // paragraph and close in another, so they can't be wrapped in `<p>`
+        if let [Node::Text(text)] = self.children.as_slice() {
+            let segments = ctx.tags.split(&[&text.value], text.position.as_ref()).remove(0);
+            let blocks = segments.iter().all(|segment| match segment {
+                Segment::Tag(tag) => mustache::is_block_tag(tag),
+                Segment::Text(text) => text.trim().is_empty(),
+            });
+            if blocks && segments.iter().any(|s| matches!(s, Segment::Tag(_))) {
+                let tags = segments
+                    .into_iter()
+                    .filter_map(|segment| match segment {
+                        Segment::Tag(tag) => Some(tag),
+                        Segment::Text(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                return ToHtmlResult::new(format!("\n{}\n", tags.join("\n")), true);
+            }
+        }
         let children = self.children.to_html(ctx);
         ToHtmlResult::from_wrapped(ctx.wrap_in_tag("p", "", children.html), children.svelte)
     }
@@ -407,11 +945,15 @@ macro_rules! node_impl {
     ($($node:ident($name:ident)),+) => {
         impl ToHtml for Node {
             fn to_html(&self, ctx: &mut Context) -> ToHtmlResult {
-                match self {
+                let res = match self {
                     $(markdown::mdast::Node::$node($name) => $name.to_html(ctx)),+
+                };
+                match self.position() {
+                    Some(pos) => ctx.mark(pos.start.offset, res),
+                    None => res,
                 }
             }
-            fn visit(&self, ctx: &mut Context) {
+            fn visit(&self, ctx: &mut Context) -> Result<(), MdsvexrsError> {
                 match self {
                     $(markdown::mdast::Node::$node($name) => $name.visit(ctx)),+
                 }
@@ -458,7 +1000,8 @@ node_impl!(
 );
 
 fn svelte_html_encode(string: String) -> String {
-    String::from("{@html `") + &string.replace("\\", "\\\\").replace("`", "\\`") + "`}"
+    let string = string.replace('\\', "\\\\").replace('`', "\\`").replace("${", "\\${");
+    String::from("{@html `") + &string + "`}"
 }
 
 fn merge(results: &[ToHtmlResult]) -> ToHtmlResult {
@@ -526,6 +1069,63 @@ pub fn html_encode(value: &str) -> String {
     result
 }
 
+/// Encodes text content and attribute values, including braces so literal ones (escaped, or
+/// from character references) can't start a svelte tag.
+fn text_encode(value: &str) -> String {
+    escape_braces(&html_encode(value))
+}
+
+/// Replaces braces in HTML with character references.
+fn escape_braces(html: &str) -> String {
+    html.replace('{', "&#123;").replace('}', "&#125;")
+}
+
+/// Encodes a URL for an attribute like markdown-rs's `sanitize`: characters that aren't allowed
+/// in URLs are percent-encoded, keeping valid `%XX` escapes, and the result is HTML encoded.
+/// Braces aren't allowed either, so they never start a svelte tag.
+fn url_encode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let bytes = value.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte == b'%'
+            && index + 2 < bytes.len()
+            && bytes[index + 1].is_ascii_alphanumeric()
+            && bytes[index + 2].is_ascii_alphanumeric()
+        {
+            result.push_str(&value[index..index + 3]);
+            index += 3;
+            continue;
+        }
+        if byte.is_ascii_alphanumeric() || b"!#$%&'()*+,-./:;=?@_~".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            // every byte of non-ASCII characters is encoded, like `encodeURI`
+            result.push_str(&format!("%{byte:02X}"));
+        }
+        index += 1;
+    }
+    html_encode(&result)
+}
+
+/// Attribute value from a string split by [`Tags::split`]. Text is encoded with `encode`, svelte
+/// tags are kept so they set the attribute, returning whether there were any.
+fn attribute(segments: Vec<Segment>, encode: fn(&str) -> String) -> (String, bool) {
+    let mut svelte = false;
+    let value = segments
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => encode(&text),
+            Segment::Tag(tag) => {
+                svelte = true;
+                tag
+            }
+        })
+        .collect();
+    (value, svelte)
+}
+
 fn finish(res: ToHtmlResult) -> String {
     if res.svelte {
         res.html
@@ -535,6 +1135,17 @@ fn finish(res: ToHtmlResult) -> String {
 }
 
 #[derive(Serialize)]
+pub struct Footnote {
+    /// Number shown in the rendered reference, in order of first reference.
+    pub index: usize,
+    pub label: String,
+    pub id: String,
+    /// How many times the footnote is referenced in the document.
+    pub references: usize,
+    pub pos: Option<Position>,
+}
+
+#[derive(Clone, Serialize)]
 pub struct Title {
     pub level: u8,
     pub text: String,
@@ -542,15 +1153,165 @@ pub struct Title {
     pub pos: Option<Position>,
 }
 
+/// Heading in the table of contents, with the headings below it nested in `children`.
+#[derive(Clone, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
+
+impl TocEntry {
+    /// Nests titles by level. A heading is a child of the closest heading before it with a lower
+    /// level, skipped levels don't add empty entries.
+    pub fn tree<'a>(titles: impl IntoIterator<Item = &'a Title>) -> Vec<TocEntry> {
+        let mut roots: Vec<TocEntry> = Vec::new();
+        for title in titles {
+            let entry = TocEntry {
+                level: title.level,
+                text: title.text.clone(),
+                id: title.id.clone(),
+                children: Vec::new(),
+            };
+            let mut siblings = &mut roots;
+            while siblings.last().is_some_and(|last| last.level < entry.level) {
+                siblings = &mut siblings.last_mut().unwrap().children;
+            }
+            siblings.push(entry);
+        }
+        roots
+    }
+}
+
+/// A `<script>` or `<style>` element taken out of the markdown.
+#[derive(Debug, Clone, Serialize)]
+pub struct Element {
+    /// Source of the whole element, tags included.
+    pub source: String,
+    pub pos: Option<Position>,
+}
+
+/// Offset of an element in the markdown source, 0 if there is none.
+fn element_offset(element: Option<&Element>) -> usize {
+    element
+        .and_then(|element| element.pos.as_ref())
+        .map_or(0, |pos| pos.start.offset)
+}
+
+impl Element {
+    /// Offset of the element's content in its source, after the opening tag.
+    fn content_start(&self) -> Result<usize, MdsvexrsError> {
+        opening_tag_len(&self.source).ok_or_else(|| MdsvexrsError::UnclosedScript {
+            pos: self.pos.clone(),
+        })
+    }
+
+    /// The opening tag, or all of the source if it isn't closed.
+    fn opening_tag(&self) -> &str {
+        &self.source[..opening_tag_len(&self.source).unwrap_or(self.source.len())]
+    }
+
+    fn is_typescript(&self) -> bool {
+        TYPESCRIPT_REGEX.is_match(self.opening_tag())
+    }
+}
+
+/// Length of the opening tag at the start of `source`, up to the first `>` that isn't in a
+/// quoted attribute value (e.g. `generics="T extends A<B>"`).
+fn opening_tag_len(source: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, b) in source.bytes().enumerate() {
+        match (quote, b) {
+            (None, b'"' | b'\'') => quote = Some(b),
+            (Some(q), b) if q == b => quote = None,
+            (None, b'>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A script written in TypeScript.
+static TYPESCRIPT_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r#"\slang\s*=\s*["']?(ts|typescript)["']?(\s|/|>|$)"#).unwrap()
+});
+
+/// Names in scripts, everything that could clash with a generated import.
+static IDENTIFIER_REGEX: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"[\p{L}_$][\p{L}\p{N}_$]*").unwrap());
+
+/// Whether `name` can be used as an identifier in generated code.
+fn is_identifier(name: &str) -> bool {
+    IDENTIFIER_REGEX.find(name).is_some_and(|found| found.as_str() == name)
+}
+
+/// Names the generated instance script declares, besides the custom tag components.
+const LAYOUT_NAME: &str = "MDXLayout";
+const PROPS_NAME: &str = "__props";
+
+/// A module script, in svelte 4 (`context="module"`) or 5 (`module`) syntax.
+static MODULE_SCRIPT_REGEX: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r#"\s(module|context\s*=\s*["']?module["']?)(\s|/|>|$)"#).unwrap()
+});
+
+/// Parsed frontmatter, whichever format it was written in.
+pub type Frontmatter = serde_json::Map<String, Value>;
+
+/// A rendered document with what build tools want to know about it, see [`Context::render`].
+#[derive(Serialize)]
+pub struct RenderOutput {
+    /// The svelte component.
+    pub code: String,
+    /// What the component exports as `metadata`: frontmatter with titles, toc and footnotes.
+    pub metadata: Frontmatter,
+    pub titles: Vec<Title>,
+    pub toc: Vec<TocEntry>,
+    /// The document's instance script, without generated imports.
+    pub script: Option<Element>,
+    /// The document's module script, without the generated `metadata` export.
+    pub module_script: Option<Element>,
+    pub style: Option<Element>,
+    pub warnings: Vec<Warning>,
+    /// Map of `code` back to the markdown, if `source_map` is enabled.
+    pub map: Option<SourceMap>,
+}
+
+/// Renders markdown documents with the same options and highlighter.
+///
+/// A context can convert any number of documents one after another: everything besides the
+/// options and the highlighter describes the current document and is reset by every
+/// [`Context::render`]. The public document fields hold the last document's values after it.
 pub struct Context {
-    pub yaml: Option<serde_json::Map<String, Value>>,
-    pub default_lang: Option<String>,
-    pub script: Option<String>,
     pub options: MdsvexrsOptions,
-    pub titles: Vec<Title>,
+    pub highlighter: Box<dyn Highlighter>,
 
-    pub syntax_set: SyntaxSet,
-    pub theme_set: ThemeSet,
+    pub yaml: Option<Frontmatter>,
+    pub default_lang: Option<String>,
+    /// The instance `<script>`.
+    pub script: Option<Element>,
+    /// `<script context="module">` or `<script module>`, merged with the `metadata` export.
+    pub module_script: Option<Element>,
+    pub style: Option<Element>,
+    /// Import path of the layout the document is wrapped in, `None` for no layout.
+    pub layout: Option<String>,
+    pub titles: Vec<Title>,
+    /// Whether the list being rendered is tight, so its items' paragraphs aren't wrapped in `<p>`.
+    tight_list: bool,
+    /// How many times each heading slug was used, for deduplication.
+    slugs: HashMap<String, usize>,
+    pub footnotes: Vec<Footnote>,
+    footnote_definitions: HashMap<String, FootnoteDefinition>,
+    definitions: HashMap<String, Definition>,
+    /// Svelte tags masked out of the source before parsing, see [`mustache`].
+    tags: Tags,
+    /// Names of generated imports and variables, renamed where the document's scripts use them.
+    names: HashMap<String, String>,
+    /// Source offsets of the output markers, see [`sourcemap`].
+    mappings: Vec<usize>,
+    /// First error that happened while rendering, reported once rendering finishes.
+    error: Option<MdsvexrsError>,
+    warnings: Vec<Warning>,
 
     // pub(crate) highlight_times: Duration,
     // pub(crate) parse_time: Duration,
@@ -558,35 +1319,198 @@ pub struct Context {
     // pub(crate) convert_time: Duration,
 }
 
-#[derive(Serialize)]
-struct HighlightRequest {
-    code: String,
-    inline: bool,
-    lang: String,
-    meta: Option<String>,
+/// What to emit for math the built-in renderer can't convert to MathML.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MathFallback {
+    /// Escaped TeX source in `<code class="math">`.
+    #[default]
+    Code,
+    /// Escaped TeX source between `$` delimiters, for client-side renderers like KaTeX auto-render.
+    Delimited,
+}
+
+impl FromStr for MathFallback {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(MathFallback::Code),
+            "delimited" => Ok(MathFallback::Delimited),
+            _ => Err(format!("unknown math fallback {s}, expected code or delimited")),
+        }
+    }
+}
+
+/// Svelte version the generated component is written for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SvelteTarget {
+    /// `<script context="module">` and `$$restProps`.
+    #[default]
+    Svelte4,
+    /// Runes mode: `<script module>`, rest props from `$props()` and the body passed to the
+    /// layout as its `children` snippet.
+    Svelte5,
+}
+
+impl FromStr for SvelteTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" | "svelte4" => Ok(SvelteTarget::Svelte4),
+            "5" | "svelte5" => Ok(SvelteTarget::Svelte5),
+            _ => Err(format!("unknown target {s}, expected svelte4 or svelte5")),
+        }
+    }
+}
+
+/// Where headings get a link to themselves, like rehype-autolink-headings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeadingLinks {
+    #[default]
+    None,
+    /// Link with the configured content before the heading text.
+    Prepend,
+    /// Link with the configured content after the heading text.
+    Append,
+    /// The heading text itself is the link.
+    Wrap,
+}
+
+impl FromStr for HeadingLinks {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(HeadingLinks::None),
+            "prepend" => Ok(HeadingLinks::Prepend),
+            "append" => Ok(HeadingLinks::Append),
+            "wrap" => Ok(HeadingLinks::Wrap),
+            _ => Err(format!(
+                "unknown heading links {s}, expected none, prepend, append or wrap"
+            )),
+        }
+    }
+}
+
+/// Pseudo element of heading links, map it to a component to render them yourself. It's an
+/// identifier so the layout can export it.
+pub const HEADING_LINK_TAG: &str = "headingLink";
+
+/// A markdown element rendered as a svelte component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomTag {
+    /// HTML element the component replaces, e.g. `a`.
+    pub tag: String,
+    /// Name the component is imported as, e.g. `Link`.
+    pub component: String,
+    /// Module the component is the default export of, e.g. `$lib/Link.svelte`. `None` imports
+    /// the layout's export named after the tag.
+    pub source: Option<String>,
+}
+
+impl FromStr for CustomTag {
+    type Err = String;
+
+    /// Parses `tag`, `tag=Component` or `tag=Component:source`. Only naming the tag imports
+    /// the uppercased tag from the layout, like `a` as `A`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tag, component) = s.split_once('=').unwrap_or((s, ""));
+        let (component, source) = match component.split_once(':') {
+            Some((component, source)) => (component, Some(source.to_string())),
+            None => (component, None),
+        };
+        if tag.is_empty() {
+            return Err(format!("custom tag {s} doesn't name an element"));
+        }
+        let component = match component {
+            "" => tag.to_ascii_uppercase(),
+            component => component.to_string(),
+        };
+        CustomTag::new(tag.to_string(), component, source)
+    }
+}
+
+impl CustomTag {
+    /// Checks that the component name is an identifier, and that the tag can be imported from
+    /// the layout if there's no source.
+    pub fn new(tag: String, component: String, source: Option<String>) -> Result<Self, String> {
+        if !is_identifier(&component) {
+            return Err(format!(
+                "component name {component} of custom tag {tag} isn't an identifier"
+            ));
+        }
+        if source.is_none() && !is_identifier(&tag) {
+            return Err(format!(
+                "custom tag {tag} can't be imported from the layout, give it a source with \
+                 {tag}={component}:source"
+            ));
+        }
+        Ok(CustomTag {
+            tag,
+            component,
+            source,
+        })
+    }
 }
 
+#[derive(Clone)]
 pub struct MdsvexrsOptions {
+    /// Layout for documents that don't pick one, empty for no layout.
     pub layout: String,
-    pub custom_tags: Vec<String>,
-    // pub path: String,
+    /// Layouts documents can pick by name with `layout: name` in frontmatter.
+    pub layouts: HashMap<String, String>,
+    /// Globs matched against `path`, with the name of the layout matching documents use unless
+    /// their frontmatter picks one. The first matching glob wins over `layout`.
+    pub layout_globs: Vec<(String, String)>,
+    /// Elements rendered as components, with their attributes passed as props.
+    pub custom_tags: Vec<CustomTag>,
+    pub math_fallback: MathFallback,
+    pub target: SvelteTarget,
+    pub heading_links: HeadingLinks,
+    /// Lowest heading level in the table of contents.
+    pub toc_min_depth: u8,
+    /// Highest heading level in the table of contents.
+    pub toc_max_depth: u8,
+    /// HTML inside prepended and appended heading links.
+    pub heading_link_content: String,
+    /// Class of heading links, empty for none.
+    pub heading_link_class: String,
+    /// Generate a source map of the output, see [`RenderOutput::map`].
+    pub source_map: bool,
+    /// Path of the converted document, for `layout_globs` and the source map.
+    pub path: Option<String>,
+    /// Treat the document as untrusted: svelte tags are rendered as text, raw HTML is
+    /// sanitized (see [`sanitize`]) and scripts in it are dropped, and links and images with a
+    /// protocol that isn't allowed get an empty URL.
+    pub safe: bool,
 }
 
 impl Context {
     pub fn new(options: MdsvexrsOptions) -> Self {
+        Self::with_highlighter(options, Box::new(SyntectHighlighter::new()))
+    }
 
-        let syntax_set: SyntaxSet = from_uncompressed_data(include_bytes!("../target/syntax_cache.packdump")).unwrap();
-        let theme_set = ThemeSet::load_defaults();
-
+    pub fn with_highlighter(options: MdsvexrsOptions, highlighter: Box<dyn Highlighter>) -> Self {
         Context {
-            // child,
-            // bufread,
-            syntax_set,
-            theme_set,
+            highlighter,
+            mappings: Vec::new(),
+            error: None,
+            warnings: Vec::new(),
             yaml: None,
             titles: Vec::new(),
+            slugs: HashMap::new(),
+            tight_list: false,
+            footnotes: Vec::new(),
+            footnote_definitions: HashMap::new(),
+            definitions: HashMap::new(),
+            tags: Tags::default(),
+            names: HashMap::new(),
             default_lang: None,
             script: None,
+            module_script: None,
+            style: None,
+            layout: None,
             options,
             // highlight_times: Duration::ZERO,
             // parse_time: Duration::ZERO,
@@ -595,152 +1519,787 @@ impl Context {
         }
     }
 
-    fn highlight(&mut self, code: HighlightRequest) -> (String, bool) {
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
+    fn highlight(
+        &mut self,
+        mut request: HighlightRequest,
+        pos: Option<&Position>,
+    ) -> (String, bool) {
         // #[cfg(not(target_arch = "wasm32"))]
         // let start = Instant::now();
 
-        let mut lang = &code.lang;
-        if lang.is_empty() {
+        if request.lang.is_empty() {
             if let Some(default) = &self.default_lang {
-                lang = default;
+                request.lang = default.clone();
             } else {
-                return self.wrap_in_tag("pre", "", self.wrap_in_tag("code", "", html_encode(&code.code)));
+                return self.wrap_in_tag("pre", "", self.wrap_in_tag("code", "", text_encode(&request.code)));
             }
         }
-        let syntax = self.syntax_set.find_syntax_by_token(lang);
-        let syntax = match syntax {
-            Some(t) => t,
-            None => {
-                return self.wrap_in_tag("pre", "", self.wrap_in_tag("code", &format!(" lang=\"{}\"", html_encode(lang)), html_encode(&code.code)));
+        let highlighted = match self.highlighter.highlight(&request) {
+            // highlighters leave the code's braces in, they must not start svelte tags when the
+            // code ends up in a component
+            Ok(Highlighted::Html(html)) => Highlighted::Html(escape_braces(&html)),
+            Ok(Highlighted::Lines(lines)) => {
+                Highlighted::Lines(lines.iter().map(|line| escape_braces(line)).collect())
+            }
+            Ok(Highlighted::Unsupported) => Highlighted::Unsupported,
+            Err(err) => {
+                self.error.get_or_insert(MdsvexrsError::Highlight {
+                    message: err.to_string(),
+                    pos: pos.cloned(),
+                });
+                Highlighted::Unsupported
+            }
+        };
+        let lang = text_encode(&request.lang);
+
+        match (highlighted, request.inline) {
+            (Highlighted::Html(html), _) => (html, false),
+            (Highlighted::Lines(lines), true) => {
+                self.wrap_in_tag("code", &format!("lang=\"{lang}\""), lines.concat())
+            }
+            (Highlighted::Unsupported, true) => {
+                self.wrap_in_tag("code", &format!("lang=\"{lang}\""), text_encode(&request.code))
+            }
+            (Highlighted::Lines(lines), false) => {
+                self.code_block(&lines, &lang, request.meta.as_deref())
+            }
+            (Highlighted::Unsupported, false) => {
+                let lines = request.code.lines().map(text_encode).collect::<Vec<_>>();
+                self.code_block(&lines, &lang, request.meta.as_deref())
+            }
+        }
+    }
+
+    /// Wraps highlighted lines in `<pre><code>`, applying the fence's meta (see [`CodeMeta`]).
+    fn code_block(&self, lines: &[String], lang: &str, meta: Option<&str>) -> (String, bool) {
+        let meta = meta.map(CodeMeta::parse).unwrap_or_default();
+        let lines = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let highlighted = if meta.is_highlighted(i + 1) {
+                    " data-highlighted-line"
+                } else {
+                    ""
+                };
+                format!("<span data-line{highlighted}>{line}</span>")
+            })
+            .join("\n");
+        let mut attrs = format!("lang=\"{lang}\"");
+        if let Some(start) = meta.line_numbers {
+            // numbers come from a CSS counter, like rehype-pretty-code
+            attrs += &format!(" data-line-numbers style=\"counter-reset: line {}\"", start - 1);
+        }
+        // a code block component gets the language too, `<code>` is just its content
+        let pre_attrs = match self.custom_tag("pre") {
+            Some(_) => format!("lang=\"{lang}\""),
+            None => String::new(),
+        };
+        let pre = self.wrap_in_tag("pre", &pre_attrs, self.wrap_in_tag("code", &attrs, lines));
+        if meta.title.is_none() && meta.caption.is_none() {
+            return (pre.0 + "\n", pre.1);
+        }
+
+        let mut content = Vec::new();
+        if let Some(title) = &meta.title {
+            content.push(ToHtmlResult::from_wrapped(
+                self.wrap_in_tag(
+                    "figcaption",
+                    &format!("data-rehype-pretty-code-title data-language=\"{lang}\""),
+                    text_encode(title),
+                ),
+                false,
+            ));
+        }
+        content.push(ToHtmlResult::from_wrapped(pre, false));
+        if let Some(caption) = &meta.caption {
+            content.push(ToHtmlResult::from_wrapped(
+                self.wrap_in_tag(
+                    "figcaption",
+                    "data-rehype-pretty-code-caption",
+                    text_encode(caption),
+                ),
+                false,
+            ));
+        }
+        let (figure, changed) =
+            self.wrap_in_tag("figure", "data-rehype-pretty-code-figure", merge(&content));
+        (figure + "\n", changed)
+    }
+
+    /// Renders TeX as MathML, or the configured fallback if it isn't supported. A component
+    /// mapped to `math` gets either one as its content, with `tex` and `display` as props.
+    fn math(&mut self, tex: &str, display: bool, pos: Option<&Position>) -> (String, bool) {
+        let mathml = match math::to_mathml(tex) {
+            Ok(mathml) => Some(mathml),
+            Err(err) => {
+                self.warn(format!("math isn't rendered to MathML: {err}"), pos);
+                None
             }
         };
-        let mut highlighter = HighlightLines::new(syntax, theme);
-
-        let mut string = String::new();
-
-        let (code_tag, code_changed) = self.resolve_tag("code");
-        let (pre_tag, pre_changed) = self.resolve_tag("pre");
-
-        match code.inline {
-            true => {
-                let regions = highlighter
-                    .highlight_line(&code.code, &self.syntax_set)
-                    .unwrap();
-                string += &format!("<{code_tag} lang=\"{}\">", html_encode(lang));
-                append_highlighted_html_for_styled_line(
-                    &regions[..],
-                    IncludeBackground::No,
-                    &mut string,
+        if self.custom_tag("math").is_some() {
+            let props = format!("tex=\"{}\" display={{{display}}}", math::escape(tex));
+            return match mathml {
+                Some(mathml) => self.wrap_in_tag(
+                    "math",
+                    &format!("xmlns=\"http://www.w3.org/1998/Math/MathML\" {props}"),
+                    mathml,
+                ),
+                None => self.wrap_in_tag("math", &props, self.math_fallback(tex, display)),
+            };
+        }
+        match mathml {
+            Some(mathml) => {
+                let display = if display { " display=\"block\"" } else { "" };
+                self.wrap_in_tag(
+                    "math",
+                    &format!("xmlns=\"http://www.w3.org/1998/Math/MathML\"{display}"),
+                    mathml,
                 )
-                .unwrap();
-                string += &format!("</{code_tag}>");
-                (string, code_changed)
-            }
-            false => {
-                string += &format!("<{pre_tag}><{code_tag} lang=\"{}\">", html_encode(lang));
-                for line in LinesWithEndings::from(&code.code) {
-                    let regions = highlighter.highlight_line(line, &self.syntax_set).unwrap();
-                    append_highlighted_html_for_styled_line(
-                        &regions[..],
-                        IncludeBackground::No,
-                        &mut string,
-                    )
-                    .unwrap();
-                }
-                string += &format!("</{code_tag}></{pre_tag}>\n");
-                (string, code_changed || pre_changed)
             }
+            None => self.math_fallback(tex, display),
+        }
+    }
+
+    fn math_fallback(&self, tex: &str, display: bool) -> (String, bool) {
+        match (self.options.math_fallback, display) {
+            (MathFallback::Code, true) => self.wrap_in_tag(
+                "pre",
+                "",
+                self.wrap_in_tag("code", "class=\"math math-display\"", math::escape(tex)),
+            ),
+            (MathFallback::Code, false) => {
+                self.wrap_in_tag("code", "class=\"math math-inline\"", math::escape(tex))
+            }
+            (MathFallback::Delimited, true) => (format!("$$ {} $$", math::escape(tex)), false),
+            (MathFallback::Delimited, false) => (format!("${}$", math::escape(tex)), false),
+        }
+    }
+
+    /// Records a warning, returned with the rendered document.
+    fn warn(&mut self, message: String, pos: Option<&Position>) {
+        self.warnings.push(Warning {
+            message,
+            pos: pos.cloned(),
+        });
+    }
+
+    /// Clears what the previous document left behind.
+    fn reset(&mut self) {
+        self.yaml = None;
+        self.default_lang = None;
+        self.script = None;
+        self.module_script = None;
+        self.style = None;
+        self.layout = None;
+        self.titles.clear();
+        self.tight_list = false;
+        self.slugs.clear();
+        self.footnotes.clear();
+        self.footnote_definitions.clear();
+        self.definitions.clear();
+        self.tags = Tags::default();
+        self.names.clear();
+        self.mappings.clear();
+        self.error = None;
+        self.warnings.clear();
+    }
+
+    /// Marks the start of a node's output as coming from `offset` in the source.
+    fn mark(&mut self, offset: usize, res: ToHtmlResult) -> ToHtmlResult {
+        if !self.options.source_map || res.html.is_empty() {
+            return res;
+        }
+        self.mappings.push(offset);
+        let marker = sourcemap::mark(self.mappings.len() - 1);
+        ToHtmlResult::new(marker + &res.html, res.svelte)
+    }
+
+    /// Marks every line of `text`, which starts at `offset` in the source.
+    fn mark_lines(&mut self, text: &str, offset: usize) -> String {
+        if !self.options.source_map {
+            return text.to_string();
+        }
+        let mut marked = String::with_capacity(text.len());
+        let mut start = 0;
+        for line in text.split_inclusive('\n') {
+            self.mappings.push(offset + start);
+            marked += &sourcemap::mark(self.mappings.len() - 1);
+            marked += line;
+            start += line.len();
+        }
+        marked
+    }
+
+    /// Table of contents of the headings within the configured depths.
+    pub fn toc(&self) -> Vec<TocEntry> {
+        let depths = self.options.toc_min_depth..=self.options.toc_max_depth;
+        TocEntry::tree(self.titles.iter().filter(|title| depths.contains(&title.level)))
+    }
+
+    /// Renders the table of contents for a `[[toc]]` or `<!-- toc -->` marker.
+    fn toc_html(&self) -> ToHtmlResult {
+        fn list(ctx: &Context, entries: &[TocEntry]) -> ToHtmlResult {
+            let items = entries
+                .iter()
+                .map(|entry| {
+                    let href = format!("href=\"#{}\"", text_encode(&entry.id));
+                    let link = ctx.wrap_in_tag("a", &href, text_encode(&entry.text));
+                    let mut content = vec![ToHtmlResult::from_wrapped(link, false)];
+                    if !entry.children.is_empty() {
+                        content.push(list(ctx, &entry.children));
+                    }
+                    ToHtmlResult::from_wrapped(ctx.wrap_in_tag("li", "", merge(&content)), false)
+                })
+                .collect::<Vec<_>>();
+            ToHtmlResult::from_wrapped(ctx.wrap_in_tag("ul", "", merge(&items)), false)
         }
+
+        let list = list(self, &self.toc());
+        let (nav, svelte) = self.wrap_in_tag("nav", "class=\"toc\"", list);
+        ToHtmlResult::new(format!("\n{nav}\n"), svelte)
+    }
+
+    /// Adds the configured link to a heading's content.
+    fn heading_link(&self, content: ToHtmlResult, id: &str) -> ToHtmlResult {
+        // `a` unless heading links are mapped to a component themselves
+        let tag = match self.custom_tag(HEADING_LINK_TAG) {
+            Some(_) => HEADING_LINK_TAG,
+            None => "a",
+        };
+        let mut attrs = format!("href=\"#{id}\"");
+        if !self.options.heading_link_class.is_empty() {
+            attrs += &format!(" class=\"{}\"", text_encode(&self.options.heading_link_class));
+        }
+        let link = |attrs: &str| {
+            let content = self.options.heading_link_content.clone();
+            ToHtmlResult::from_wrapped(self.wrap_in_tag(tag, attrs, content), false)
+        };
+        // the text already says what the link is, so extra links are hidden from screen readers
+        let hidden = attrs.clone() + " aria-hidden=\"true\" tabindex=\"-1\"";
+        match self.options.heading_links {
+            HeadingLinks::None => content,
+            HeadingLinks::Prepend => merge(&[link(&hidden), content]),
+            HeadingLinks::Append => merge(&[content, link(&hidden)]),
+            HeadingLinks::Wrap => ToHtmlResult::from_wrapped(
+                self.wrap_in_tag(tag, &attrs, content),
+                false,
+            ),
+        }
+    }
+
+    /// Makes a heading slug unique in the document by adding `-1`, `-2`… like github-slugger.
+    fn unique_slug(&mut self, slug: String) -> String {
+        let mut unique = slug.clone();
+        while self.slugs.contains_key(&unique) {
+            let count = self.slugs.entry(slug.clone()).or_default();
+            *count += 1;
+            unique = format!("{slug}-{count}");
+        }
+        self.slugs.insert(unique.clone(), 0);
+        unique
+    }
+
+    /// Takes a trailing `{#id}` off the end of the heading text, returning the id.
+    fn heading_id(&self, text: &mut Text) -> Option<String> {
+        let mut segments = self.tags.split(&[&text.value], text.position.as_ref()).remove(0);
+        let Some(Segment::Tag(tag)) = segments.pop() else {
+            return None;
+        };
+        let id = HEADING_ID_REGEX.captures(&tag)?["id"].to_string();
+        match segments.last() {
+            // the id has to be separated from the text
+            Some(Segment::Text(before)) if before.ends_with(char::is_whitespace) => {}
+            None => {}
+            _ => return None,
+        }
+        // the id is the last segment, so its placeholder ends the text
+        text.value.truncate(text.value.len() - tag.len());
+        text.value.truncate(text.value.trim_end().len());
+        Some(id)
+    }
+
+    /// Puts masked Svelte tags in `value` of the node at `pos` back as plain text.
+    fn restore(&self, value: &str, pos: Option<&Position>) -> String {
+        let [value] = self.restore_all([value], pos);
+        value
+    }
+
+    /// Splits strings of the node at `pos`, given in source order, into text and svelte tags.
+    fn split_all<const N: usize>(
+        &self,
+        values: [&str; N],
+        pos: Option<&Position>,
+    ) -> [Vec<Segment>; N] {
+        let mut split = self.tags.split(&values, pos).into_iter();
+        std::array::from_fn(|_| split.next().unwrap_or_default())
+    }
+
+    /// Like [`Context::restore`], for several strings of a node, given in source order.
+    fn restore_all<const N: usize>(
+        &self,
+        values: [&str; N],
+        pos: Option<&Position>,
+    ) -> [String; N] {
+        let restored = self.tags.restore(&values, pos);
+        std::array::from_fn(|i| restored[i].clone())
     }
 
     fn resolve_tag(&self, tag: &str) -> (String, bool) {
-        if self.options.custom_tags.contains(&tag.to_string()) {
-            (tag.to_ascii_uppercase(), true)
-        } else {
-            (tag.to_string(), false)
+        match self.custom_tag(tag) {
+            Some(custom) => (self.local_name(&custom.component).to_string(), true),
+            None => (tag.to_string(), false),
         }
     }
 
+    /// Component the element is rendered as, if any.
+    fn custom_tag(&self, tag: &str) -> Option<&CustomTag> {
+        // tags without a source are imported from the layout, so there are none without one
+        self.options
+            .custom_tags
+            .iter()
+            .find(|custom| custom.tag == tag && (custom.source.is_some() || self.layout.is_some()))
+    }
+
+    /// Names for the generated imports and variables that no script of the document uses,
+    /// adding `_1`, `_2`… where one does.
+    fn local_names(&self) -> HashMap<String, String> {
+        // any word counts, declared or not, so nothing the scripts reference gets shadowed
+        let mut used = [&self.script, &self.module_script]
+            .into_iter()
+            .flatten()
+            .flat_map(|script| IDENTIFIER_REGEX.find_iter(&script.source))
+            .map(|name| name.as_str().to_string())
+            .collect::<HashSet<_>>();
+        let generated = [LAYOUT_NAME, PROPS_NAME]
+            .into_iter()
+            .chain(self.options.custom_tags.iter().map(|custom| custom.component.as_str()));
+        let mut names = HashMap::new();
+        for name in generated {
+            if names.contains_key(name) || !used.contains(name) {
+                continue;
+            }
+            let local = (1..)
+                .map(|i| format!("{name}_{i}"))
+                .find(|local| !used.contains(local))
+                .unwrap();
+            used.insert(local.clone());
+            names.insert(name.to_string(), local);
+        }
+        names
+    }
+
+    /// Name the generated code uses for `name`, see [`Context::local_names`].
+    fn local_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.names.get(name).map_or(name, String::as_str)
+    }
+
+    /// Import statements for the layout and custom tags the document uses.
+    fn imports(&self) -> String {
+        let mut imports = String::new();
+        if let Some(layout) = &self.layout {
+            let from_layout = self
+                .options
+                .custom_tags
+                .iter()
+                .filter(|custom| custom.source.is_none())
+                .map(|custom| format!("{} as {}", custom.tag, self.local_name(&custom.component)))
+                .join(", ");
+            let layout_name = self.local_name(LAYOUT_NAME);
+            if from_layout.is_empty() {
+                imports += &format!("import {layout_name} from \"{layout}\";");
+            } else {
+                imports +=
+                    &format!("import {layout_name}, {{ {from_layout} }} from \"{layout}\";");
+            }
+        }
+        // several elements can share a component, e.g. all headings
+        let sourced = self
+            .options
+            .custom_tags
+            .iter()
+            .filter_map(|custom| Some((&custom.component, custom.source.as_ref()?)))
+            .unique();
+        for (component, source) in sourced {
+            let component = self.local_name(component);
+            imports += &format!("import {component} from \"{source}\";");
+        }
+        imports
+    }
+
     fn wrap_in_tag(&self, tag: &str, opts: &str, content: impl Into<Wrappable>) -> (String, bool) {
         let content: Wrappable = content.into();
         let (tag, changed) = self.resolve_tag(tag);
         (format!("<{tag} {opts}>{}</{tag}>", content.html), changed || content.svelte)
     }
 
-    fn resolve_layout(&self) -> &str {
-        &self.options.layout
+    /// Renders the GitHub-style footnote section for every referenced footnote.
+    fn footnote_section(&mut self) -> ToHtmlResult {
+        let mut items = Vec::new();
+        // footnotes can reference other footnotes, which pushes new ones while rendering
+        let mut i = 0;
+        while i < self.footnotes.len() {
+            let definition = self.footnote_definitions[&self.footnotes[i].label].clone();
+            let mut children = definition.children;
+            let last_paragraph = match children.last() {
+                Some(Node::Paragraph(_)) => children.pop(),
+                _ => None,
+            };
+            let mut content = vec![children.to_html(self)];
+
+            let footnote = &self.footnotes[i];
+            let backrefs = (1..=footnote.references)
+                .map(|reference| {
+                    let extra = if reference == 1 {
+                        String::new()
+                    } else {
+                        format!("<sup>{reference}</sup>")
+                    };
+                    self.wrap_in_tag(
+                        "a",
+                        &format!(
+                            "href=\"#{}\" data-footnote-backref aria-label=\"Back to reference {}\" class=\"data-footnote-backref\"",
+                            footnote_ref_id(footnote.index, reference),
+                            footnote.index
+                        ),
+                        format!("↩{extra}"),
+                    )
+                })
+                .map(|backref| ToHtmlResult::from_wrapped(backref, false))
+                .collect::<Vec<_>>();
+            let backrefs = merge(&backrefs);
+
+            if let Some(Node::Paragraph(paragraph)) = last_paragraph {
+                let paragraph = merge(&[
+                    paragraph.children.to_html(self),
+                    ToHtmlResult::new(" ".to_string(), false),
+                    backrefs,
+                ]);
+                content.push(ToHtmlResult::from_wrapped(
+                    self.wrap_in_tag("p", "", paragraph),
+                    false,
+                ));
+            } else {
+                content.push(backrefs);
+            }
+
+            let id = self.footnotes[i].id.clone();
+            let content = merge(&content);
+            items.push(ToHtmlResult::from_wrapped(
+                self.wrap_in_tag("li", &format!("id=\"{id}\""), content),
+                false,
+            ));
+            i += 1;
+        }
+
+        if items.is_empty() {
+            return ToHtmlResult::empty();
+        }
+
+        let list = merge(&items);
+        let heading = ToHtmlResult::from_wrapped(
+            self.wrap_in_tag(
+                "h2",
+                &format!("class=\"sr-only\" id=\"{FOOTNOTE_LABEL_ID}\""),
+                "Footnotes".to_string(),
+            ),
+            false,
+        );
+        let list = ToHtmlResult::from_wrapped(self.wrap_in_tag("ol", "", list), false);
+        let content = merge(&[heading, list]);
+        let (section, svelte) =
+            self.wrap_in_tag("section", "data-footnotes class=\"footnotes\"", content);
+        ToHtmlResult::new(format!("\n{section}\n"), svelte)
     }
 
-    pub fn convert(&mut self, input: &str) -> String {
+    /// Stores parsed frontmatter and applies the settings it contains.
+    fn set_frontmatter(
+        &mut self,
+        value: Frontmatter,
+        pos: Option<Position>,
+    ) -> Result<(), MdsvexrsError> {
+        if let Some(val) = value.get("defaultLang") {
+            let lang = val.as_str().ok_or(MdsvexrsError::DefaultLang { pos: pos.clone() })?;
+            self.default_lang = Some(lang.to_string());
+        }
+        match value.get("layout") {
+            None => {}
+            Some(Value::Bool(false)) => self.layout = None,
+            Some(Value::String(name)) => {
+                let layout = self.options.layouts.get(name).ok_or_else(|| {
+                    MdsvexrsError::UnknownLayout {
+                        name: name.clone(),
+                        pos: pos.clone(),
+                    }
+                })?;
+                self.layout = Some(layout.clone());
+            }
+            Some(_) => return Err(MdsvexrsError::InvalidLayout { pos }),
+        }
+        self.yaml = Some(value);
+        Ok(())
+    }
+
+    /// ` lang="ts"` for a generated script if the document's other script is TypeScript, so
+    /// both use the same language.
+    fn script_lang(&self, other: &Option<Element>) -> &'static str {
+        match other {
+            Some(script) if script.is_typescript() => " lang=\"ts\"",
+            _ => "",
+        }
+    }
+
+    /// Stores the `<script>` or `<style>` element at `start..end` in the source.
+    fn set_element(&mut self, source: &str, start: usize, end: usize) -> Result<(), MdsvexrsError> {
+        let value = &source[start..end];
+        let element = Element {
+            source: value.to_string(),
+            pos: Some(Position {
+                start: point_at(source, start),
+                end: point_at(source, end),
+            }),
+        };
+        let open = element.opening_tag();
+        let (slot, name) = if open[1..].to_ascii_lowercase().starts_with("style") {
+            (&mut self.style, "<style>")
+        } else if MODULE_SCRIPT_REGEX.is_match(open) {
+            (&mut self.module_script, "module <script>")
+        } else {
+            (&mut self.script, "instance <script>")
+        };
+        if slot.is_some() {
+            return Err(MdsvexrsError::DuplicateElement {
+                element: name,
+                pos: element.pos,
+            });
+        }
+        *slot = Some(element);
+        Ok(())
+    }
+
+    /// Layout for the document before frontmatter is read, from `layout_globs` or `layout`.
+    fn default_layout(&self) -> Result<Option<String>, MdsvexrsError> {
+        if let Some(path) = &self.options.path {
+            for (glob, name) in &self.options.layout_globs {
+                let matcher = globset::Glob::new(glob)
+                    .map_err(|err| MdsvexrsError::LayoutGlob {
+                        glob: glob.clone(),
+                        message: err.kind().to_string(),
+                    })?
+                    .compile_matcher();
+                if !matcher.is_match(path) {
+                    continue;
+                }
+                let layout = self.options.layouts.get(name).ok_or_else(|| {
+                    MdsvexrsError::UnknownLayout {
+                        name: name.clone(),
+                        pos: None,
+                    }
+                })?;
+                return Ok(Some(layout.clone()));
+            }
+        }
+        Ok(Some(self.options.layout.clone()).filter(|layout| !layout.is_empty()))
+    }
+
+    /// Checks that every component name imports one component, so the imports don't clash.
+    /// Elements can share a component, e.g. all headings.
+    fn check_components(&self) -> Result<(), MdsvexrsError> {
+        let mut sources = HashMap::new();
+        for custom in &self.options.custom_tags {
+            let generated = [LAYOUT_NAME, PROPS_NAME].contains(&custom.component.as_str());
+            let source = sources.entry(custom.component.as_str()).or_insert(&custom.source);
+            if generated || *source != &custom.source {
+                return Err(MdsvexrsError::ComponentClash {
+                    component: custom.component.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts a markdown document to a svelte component.
+    pub fn convert(&mut self, input: &str) -> Result<String, MdsvexrsError> {
+        Ok(self.render(input)?.code)
+    }
+
+    /// Like [`Context::convert`], also returning the document's metadata, scripts and warnings.
+    pub fn render(&mut self, input: &str) -> Result<RenderOutput, MdsvexrsError> {
         // #[cfg(not(target_arch = "wasm32"))]
         // let start = Instant::now();
-        let ast = markdown::to_mdast(input, &DEFAULT_MD_OPTIONS).unwrap();
+        self.reset();
+        self.check_components()?;
+        self.layout = self.default_layout()?;
+        let source = input;
+        let blanked;
+        let input = match json_frontmatter(input)? {
+            Some((value, len)) => {
+                let end = point_at(input, len);
+                self.set_frontmatter(
+                    value,
+                    Some(Position {
+                        start: Point::new(1, 1, 0),
+                        end,
+                    }),
+                )?;
+                // blank out the frontmatter instead of cutting it so positions stay the same
+                blanked = blank(&input[..len]) + &input[len..];
+                &blanked
+            }
+            None => input,
+        };
+        let elements = mustache::elements(input);
+        // without tags every brace is text, and text is always escaped
+        let (mut input, tags) = match self.options.safe {
+            true => (input.to_string(), Tags::default()),
+            false => Tags::mask(input),
+        };
+        self.tags = tags;
+        for (start, end) in elements {
+            input.replace_range(start..end, &blank(&source[start..end]));
+            // scripts and styles of untrusted documents are dropped
+            if !self.options.safe {
+                self.set_element(source, start, end)?;
+            }
+        }
+        self.names = self.local_names();
+        let ast = markdown::to_mdast(&input, &DEFAULT_MD_OPTIONS)?;
         // #[cfg(not(target_arch = "wasm32"))] {
         //     self.parse_time = start.elapsed();
         // }
         // #[cfg(not(target_arch = "wasm32"))]
         // let start = Instant::now();
-        ast.visit(self);
+        ast.visit(self)?;
         // #[cfg(not(target_arch = "wasm32"))] {
         //     self.visit_time = start.elapsed();
         // }
 
-        if let Some(yaml) = &self.yaml {
-            if let Some(val) = yaml.get("defaultLang") {
-                self.default_lang = Some(val.as_str().unwrap().to_string());
-            }
-        }
-
         // #[cfg(not(target_arch = "wasm32"))]
         // let start = Instant::now();
         let res = ast.to_html(self);
-        let html = finish(res);
+        let footnotes = self.footnote_section();
+        let html = finish(merge(&[res, footnotes]));
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         // #[cfg(not(target_arch = "wasm32"))] {
         //     self.convert_time = start.elapsed();
         // }
 
-        if let Some(yaml) = &mut self.yaml {
-            yaml.insert(
-                "titles".to_string(),
-                serde_json::to_value(&self.titles).unwrap(),
-            );
-        }
-
-        let value = self
-            .script
-            .clone()
-            .unwrap_or_else(|| String::from("<script></script>"));
-
-        let script = {
-            let end = value.find('>').expect(
-                "Unclosed script tag (found <script but not >). May be a bug with Markdown parser.",
-            ) + 1;
-            let mut script = value[..end].to_string();
-            let layout = self.resolve_layout();
-            if self.options.custom_tags.is_empty() {
-                script += format!("import MDXLayout from \"{}\";", layout).as_str();
+        let toc = self.toc();
+        // documents without frontmatter get the generated metadata too
+        let yaml = self.yaml.get_or_insert_with(Frontmatter::new);
+        yaml.insert(
+            "titles".to_string(),
+            serde_json::to_value(&self.titles).unwrap(),
+        );
+        yaml.insert(
+            "toc".to_string(),
+            serde_json::to_value(&toc).unwrap(),
+        );
+        yaml.insert(
+            "footnotes".to_string(),
+            serde_json::to_value(&self.footnotes).unwrap(),
+        );
+
+        let imports = self.imports();
+        let script = self.script.clone();
+        let script_offset = element_offset(script.as_ref());
+        let (script, rest_props) = if imports.is_empty() {
+            let script = script.map(|script| script.source).unwrap_or_default();
+            (self.mark_lines(&script, script_offset), String::new())
+        } else {
+            let generated = format!("<script{}></script>", self.script_lang(&self.module_script));
+            let end = match &script {
+                Some(script) => script.content_start()?,
+                None => generated.len() - "</script>".len(),
+            };
+            let from_document = script.is_some();
+            let value = script.map_or(generated, |script| script.source);
+            let mut script = value[..end].to_string() + &imports;
+            // runes allow a single $props() call, so rest props are only forwarded when the
+            // user script doesn't take props itself
+            let rest_props = match self.options.target {
+                _ if self.layout.is_none() => String::new(),
+                SvelteTarget::Svelte4 => " {...$$restProps}".to_string(),
+                SvelteTarget::Svelte5 if value.contains("$props(") => String::new(),
+                SvelteTarget::Svelte5 => {
+                    let props = self.local_name(PROPS_NAME);
+                    script += &format!("let {{ ...{props} }} = $props();");
+                    format!(" {{...{props}}}")
+                }
+            };
+            // generated code has no source to point at
+            if from_document {
+                script += &self.mark_lines(&value[end..], script_offset + end);
             } else {
-                let custom_tags = self.options.custom_tags
-                    .iter()
-                    .map(|tag| format!("{} as {}", tag, tag.to_ascii_uppercase()))
-                    .join(", ");
-                script += format!("import MDXLayout, {{ {} }} from \"{}\";", custom_tags, layout).as_str();
-            }
-            script += &value[end..];
-            script
+                script += &value[end..];
+            }
+            (script, rest_props)
         };
 
-        let frontmatter =
-            (|| serde_json::to_string(self.yaml.as_ref()?).ok())().unwrap_or("{}".to_string());
+        // `<` is escaped so no string in the frontmatter can close the script
+        let frontmatter = (|| serde_json::to_string(self.yaml.as_ref()?).ok())()
+            .unwrap_or("{}".to_string())
+            .replace('<', "\\u003c");
+        let metadata = format!("export const metadata = {frontmatter}");
+        // the document's module script gets the export, keeping its attributes like `lang`
+        let module = match self.module_script.clone() {
+            Some(module) => {
+                let start = module.content_start()?;
+                let offset = element_offset(Some(&module)) + start;
+                let content = self.mark_lines(&module.source[start..], offset);
+                format!("{}{metadata};{content}", &module.source[..start])
+            }
+            None => {
+                let lang = self.script_lang(&self.script);
+                match self.options.target {
+                    SvelteTarget::Svelte4 => {
+                        format!("<script context=\"module\"{lang}>{metadata}</script>")
+                    }
+                    SvelteTarget::Svelte5 => format!("<script module{lang}>{metadata}</script>"),
+                }
+            }
+        };
+        let style = match self.style.clone() {
+            Some(style) => {
+                let offset = element_offset(Some(&style));
+                format!("\n{}", self.mark_lines(&style.source, offset))
+            }
+            None => String::new(),
+        };
+        let body = match &self.layout {
+            // in svelte 5 the body becomes the layout's `children` snippet
+            Some(_) => {
+                let layout = self.local_name(LAYOUT_NAME);
+                format!("<{layout} {{...metadata}}{rest_props}>\n{html}\n</{layout}>")
+            }
+            None => html,
+        };
 
-        format!(
-            "<script context=\"module\">export const metadata = {frontmatter}</script>
+        let output = format!(
+            "{module}
 {script}
-<MDXLayout {{...metadata}} {{...$$restProps}}>
-{html}
-</MDXLayout>"
-        )
+{body}{style}"
+        );
+        let (code, map) = if self.options.source_map {
+            let path = self.options.path.as_deref();
+            let (code, map) = sourcemap::extract(&output, source, &self.mappings, path);
+            (code, Some(map))
+        } else {
+            (output, None)
+        };
+        Ok(RenderOutput {
+            code,
+            metadata: self.yaml.clone().unwrap_or_default(),
+            titles: self.titles.clone(),
+            toc,
+            script: self.script.clone(),
+            module_script: self.module_script.clone(),
+            style: self.style.clone(),
+            warnings: std::mem::take(&mut self.warnings),
+            map,
+        })
     }
 
     // #[cfg(not(target_arch = "wasm32"))]
@@ -1,39 +1,309 @@
-use std::io::{stdin, Read};
+use std::{
+    collections::HashMap,
+    fs, io,
+    io::{stdin, Read},
+    path::{Path, PathBuf},
+    process::{exit, Command},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
 
-use clap::{Parser, Subcommand};
-use mdsvexrs::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use mdsvexrs::{
+    highlight::{
+        ExternalHighlighter, HighlightStyle, Highlighter, NoHighlighter, SyntectHighlighter,
+    },
+    Context, CustomTag, HeadingLinks, MathFallback, MdsvexrsOptions, SvelteTarget,
+};
+use markdown::unist::Position;
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    /// Layout for documents that don't pick one, no layout if omitted
     #[arg(short, long)]
-    layout: String,
-    #[arg(short, long, default_value = "")]
-    custom_tags: Vec<String>,
-    // #[arg(short, long)]
-    // path: String,
+    layout: Option<String>,
+    /// Named layout documents can pick with `layout: name` in frontmatter, as name=path
+    #[arg(long = "named-layout", value_parser = pair)]
+    layouts: Vec<(String, String)>,
+    /// Named layout for documents whose --path matches a glob, as glob=name
+    #[arg(long = "layout-glob", value_parser = pair)]
+    layout_globs: Vec<(String, String)>,
+    /// Path of the document, matched against --layout-glob
+    #[arg(short, long)]
+    path: Option<String>,
+    /// Element rendered as a component: tag (imports TAG from the layout), tag=Component (from
+    /// the layout) or tag=Component:source (default export of source)
+    #[arg(short, long)]
+    custom_tags: Vec<CustomTag>,
+    /// What to emit for math that can't be rendered to MathML (code or delimited)
+    #[arg(long, default_value = "code")]
+    math_fallback: MathFallback,
+    /// Svelte version to generate code for (svelte4 or svelte5)
+    #[arg(long, default_value = "svelte4")]
+    target: SvelteTarget,
+    /// Where headings get a link to themselves (none, prepend, append or wrap), map the
+    /// headingLink tag to render it with a component
+    #[arg(long, default_value = "none")]
+    heading_links: HeadingLinks,
+    /// HTML inside prepended and appended heading links
+    #[arg(long, default_value = "#")]
+    heading_link_content: String,
+    /// Class of heading links
+    #[arg(long, default_value = "")]
+    heading_link_class: String,
+    /// Lowest heading level in the table of contents
+    #[arg(long, default_value_t = 1)]
+    toc_min_depth: u8,
+    /// Highest heading level in the table of contents
+    #[arg(long, default_value_t = 6)]
+    toc_max_depth: u8,
+    #[arg(long, value_enum, default_value_t = HighlighterKind::Syntect)]
+    highlighter: HighlighterKind,
+    /// Command to run for the external highlighter, e.g. "node highlighter/index.js"
+    #[arg(long, required_if_eq("highlighter", "external"))]
+    highlighter_command: Option<String>,
+    /// Syntect theme name, or path to a .tmTheme file
+    #[arg(long)]
+    theme: Option<String>,
+    /// Emit syntect scope classes instead of inline styles, see the css subcommand
+    #[arg(long)]
+    highlight_classes: bool,
+    /// Write a source map of the output to this file
+    #[arg(long)]
+    source_map: Option<PathBuf>,
+    /// Treat the input as untrusted: no svelte tags, sanitized HTML and only safe URL protocols
+    #[arg(long)]
+    safe: bool,
+    /// Print the code with metadata, toc, scripts and warnings as JSON
+    #[arg(long)]
+    json: bool,
     #[arg(long)]
     timings: bool,
 }
 
+#[derive(Subcommand)]
+enum Commands {
+    /// Print the stylesheet for --highlight-classes output
+    Css {
+        /// Syntect theme name, or path to a .tmTheme file
+        #[arg(long)]
+        theme: Option<String>,
+    },
+    /// Compile every .md and .svx file in a directory to .svelte, using the options given
+    /// before the subcommand
+    Build {
+        /// Directory with the markdown files, searched recursively
+        src: PathBuf,
+        /// Directory for the .svelte files, with the same structure as src
+        #[arg(short, long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum HighlighterKind {
+    Syntect,
+    None,
+    /// Long-lived process speaking the line-delimited JSON protocol of highlighter/index.js
+    External,
+}
+
+fn pair(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got {value}"))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+fn syntect(theme: Option<&str>) -> SyntectHighlighter {
+    let mut highlighter = SyntectHighlighter::new();
+    if let Some(theme) = theme {
+        if let Err(err) = highlighter.use_theme(theme) {
+            eprintln!("error: {err}");
+            exit(1);
+        }
+    }
+    highlighter
+}
+
+fn highlighter(args: &Args) -> Box<dyn Highlighter> {
+    match args.highlighter {
+        HighlighterKind::Syntect => {
+            let mut highlighter = syntect(args.theme.as_deref());
+            if args.highlight_classes {
+                highlighter.style = HighlightStyle::Classes;
+            }
+            Box::new(highlighter)
+        }
+        HighlighterKind::None => Box::new(NoHighlighter),
+        HighlighterKind::External => {
+            let command = args.highlighter_command.as_deref().unwrap_or_default();
+            let mut parts = command.split_whitespace();
+            let Some(program) = parts.next() else {
+                eprintln!("error: --highlighter-command is empty");
+                exit(1);
+            };
+            let mut command = Command::new(program);
+            command.args(parts);
+            Box::new(ExternalHighlighter::new(command))
+        }
+    }
+}
+
+fn options(args: &Args) -> MdsvexrsOptions {
+    MdsvexrsOptions {
+        layout: args.layout.clone().unwrap_or_default(),
+        layouts: args.layouts.iter().cloned().collect::<HashMap<_, _>>(),
+        layout_globs: args.layout_globs.clone(),
+        custom_tags: args.custom_tags.clone(),
+        math_fallback: args.math_fallback,
+        target: args.target,
+        heading_links: args.heading_links,
+        toc_min_depth: args.toc_min_depth,
+        toc_max_depth: args.toc_max_depth,
+        heading_link_content: args.heading_link_content.clone(),
+        heading_link_class: args.heading_link_class.clone(),
+        source_map: args.source_map.is_some(),
+        path: args.path.clone(),
+        safe: args.safe,
+    }
+}
+
+/// `file:line:column: message`, or `file: message` without a position.
+fn located(file: &Path, pos: Option<&Position>, message: &str) -> String {
+    match pos {
+        Some(pos) => {
+            let (line, column) = (pos.start.line, pos.start.column);
+            format!("{}:{line}:{column}: {message}", file.display())
+        }
+        None => format!("{}: {message}", file.display()),
+    }
+}
+
+/// Adds the `.md` and `.svx` files in `dir` and its subdirectories to `files`.
+fn markdown_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            markdown_files(&path, files)?;
+        } else if matches!(path.extension().and_then(|ext| ext.to_str()), Some("md" | "svx")) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Compiles `file` from `src` to the same place in `out`, returning the error message if it
+/// fails.
+fn build_file(ctx: &mut Context, src: &Path, out: &Path, file: &Path) -> Result<(), String> {
+    let relative = file.strip_prefix(src).unwrap_or(file);
+    let input = fs::read_to_string(file).map_err(|err| located(file, None, &err.to_string()))?;
+    // layout globs match the path inside src
+    ctx.options.path = Some(relative.to_string_lossy().into_owned());
+    let output = ctx
+        .render(&input)
+        .map_err(|err| located(file, err.pos(), &err.message()))?;
+    for warning in &output.warnings {
+        eprintln!("warning: {}", located(file, warning.pos.as_ref(), &warning.message));
+    }
+
+    let target = out.join(relative).with_extension("svelte");
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir).map_err(|err| located(dir, None, &err.to_string()))?;
+    }
+    fs::write(&target, output.code).map_err(|err| located(&target, None, &err.to_string()))
+}
+
+/// Compiles all markdown files in `src` on every core. Files that fail are reported and
+/// skipped, the exit code is 1 if any did.
+fn build(args: &Args, src: &Path, out: &Path) {
+    let mut files = Vec::new();
+    if let Err(err) = markdown_files(src, &mut files) {
+        eprintln!("error: {}", located(src, None, &err.to_string()));
+        exit(1);
+    }
+    let mut options = options(args);
+    options.source_map = false;
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(files.len());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                // syntaxes and themes are shared, so a context per thread is cheap
+                let mut ctx = Context::with_highlighter(options.clone(), highlighter(args));
+                while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(err) = build_file(&mut ctx, src, out, file) {
+                        eprintln!("error: {err}");
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner();
+    eprintln!("compiled {} of {} files", files.len() - failed, files.len());
+    if failed > 0 {
+        exit(1);
+    }
+}
 
 fn main() {
     let args = Args::parse();
 
-    let mut ctx = Context::new(mdsvexrs::MdsvexrsOptions {
-        layout: args.layout,
-        custom_tags: args.custom_tags,
-        // path: args.path,
-    });
+    if let Some(Commands::Css { theme }) = &args.command {
+        match syntect(theme.as_deref()).css() {
+            Ok(css) => print!("{css}"),
+            Err(err) => {
+                eprintln!("error: {err}");
+                exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Commands::Build { src, out }) = &args.command {
+        build(&args, src, out);
+        return;
+    }
+
+    let mut ctx = Context::with_highlighter(options(&args), highlighter(&args));
 
     let mut input = String::new();
     stdin().read_to_string(&mut input).unwrap();
-    let output = ctx.convert(&input);
+    let output = match ctx.render(&input) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("error: {err}");
+            exit(1);
+        }
+    };
+
+    if let (Some(path), Some(map)) = (&args.source_map, &output.map) {
+        if let Err(err) = fs::write(path, map.to_json()) {
+            eprintln!("error: can't write {}: {err}", path.display());
+            exit(1);
+        }
+    }
 
     if args.timings {
         ctx.print_timings();
         return;
     }
 
-    print!("{output}");
+    if args.json {
+        println!("{}", serde_json::to_string(&output).unwrap());
+        return;
+    }
+    for warning in &output.warnings {
+        eprintln!("warning: {warning}");
+    }
+    print!("{}", output.code);
 }
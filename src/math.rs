@@ -0,0 +1,1013 @@
+//! Minimal TeX to MathML converter, so math renders without client-side KaTeX.
+//!
+//! Covers the commonly used subset of LaTeX math: scripts, fractions, roots, greek letters
+//! and symbols, big operators, functions, accents, fonts, `\left`/`\right` and the matrix-like
+//! environments. Anything else results in a [`MathError`] so the caller can fall back.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MathError {
+    UnsupportedCommand(String),
+    UnsupportedEnvironment(String),
+    /// A `}`, `&`, `\\`, `\right` or `\end` without its opening counterpart.
+    Unexpected(String),
+    /// Input ended while a group, argument or environment was still open.
+    UnexpectedEnd,
+    DoubleScript,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::UnsupportedCommand(name) => write!(f, "unsupported command \\{name}"),
+            MathError::UnsupportedEnvironment(name) => write!(f, "unsupported environment {name}"),
+            MathError::Unexpected(token) => write!(f, "unexpected {token}"),
+            MathError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            MathError::DoubleScript => write!(f, "double superscript or subscript"),
+        }
+    }
+}
+
+impl std::error::Error for MathError {}
+
+/// Converts TeX source into the inner content of a `<math>` element.
+///
+/// The TeX source is kept as an `application/x-tex` annotation. The output never contains
+/// raw `{` or `}`, so it is safe to place in svelte markup.
+pub fn to_mathml(tex: &str) -> Result<String, MathError> {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+        variant: None,
+    };
+    let row = parser.parse_row()?;
+    match parser.next_token() {
+        Token::End => {}
+        token => return Err(MathError::Unexpected(token.to_string())),
+    }
+    Ok(format!(
+        "<semantics><mrow>{}</mrow><annotation encoding=\"application/x-tex\">{}</annotation></semantics>",
+        row,
+        escape(tex)
+    ))
+}
+
+/// HTML escaping that also turns svelte's `{` and `}` into character references.
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '{' => result.push_str("&#123;"),
+            '}' => result.push_str("&#125;"),
+            '`' => result.push_str("&#96;"),
+            '\\' => result.push_str("&#92;"),
+            '$' => result.push_str("&#36;"),
+            '\0' => result.push('\u{FFFD}'),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Command(String),
+    Char(char),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Command(name) => write!(f, "\\{name}"),
+            Token::Char(c) => write!(f, "{c}"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Normal,
+    Bold,
+    Italic,
+    BoldItalic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+impl Variant {
+    fn from_command(name: &str) -> Option<Self> {
+        Some(match name {
+            "mathrm" | "mathup" => Variant::Normal,
+            "mathbf" | "textbf" => Variant::Bold,
+            "mathit" | "textit" => Variant::Italic,
+            "boldsymbol" | "bm" => Variant::BoldItalic,
+            "mathcal" | "mathscr" => Variant::Script,
+            "mathfrak" => Variant::Fraktur,
+            "mathbb" => Variant::DoubleStruck,
+            "mathsf" | "textsf" => Variant::SansSerif,
+            "mathtt" | "texttt" => Variant::Monospace,
+            _ => return None,
+        })
+    }
+
+    /// Maps a character into the Mathematical Alphanumeric Symbols block.
+    fn map(self, c: char) -> char {
+        // (capital A, digit zero) for each variant
+        let (letters, digits) = match self {
+            Variant::Normal => return c,
+            Variant::Bold => (0x1D400, Some(0x1D7CE)),
+            Variant::Italic => (0x1D434, None),
+            Variant::BoldItalic => (0x1D468, None),
+            Variant::Script => (0x1D49C, None),
+            Variant::Fraktur => (0x1D504, None),
+            Variant::DoubleStruck => (0x1D538, Some(0x1D7D8)),
+            Variant::SansSerif => (0x1D5A0, Some(0x1D7E2)),
+            Variant::Monospace => (0x1D670, Some(0x1D7F6)),
+        };
+        // letters that were encoded before the block existed and left holes in it
+        let hole = match (self, c) {
+            (Variant::Italic, 'h') => Some('ℎ'),
+            (Variant::Script, 'B') => Some('ℬ'),
+            (Variant::Script, 'E') => Some('ℰ'),
+            (Variant::Script, 'F') => Some('ℱ'),
+            (Variant::Script, 'H') => Some('ℋ'),
+            (Variant::Script, 'I') => Some('ℐ'),
+            (Variant::Script, 'L') => Some('ℒ'),
+            (Variant::Script, 'M') => Some('ℳ'),
+            (Variant::Script, 'R') => Some('ℛ'),
+            (Variant::Script, 'e') => Some('ℯ'),
+            (Variant::Script, 'g') => Some('ℊ'),
+            (Variant::Script, 'o') => Some('ℴ'),
+            (Variant::Fraktur, 'C') => Some('ℭ'),
+            (Variant::Fraktur, 'H') => Some('ℌ'),
+            (Variant::Fraktur, 'I') => Some('ℑ'),
+            (Variant::Fraktur, 'R') => Some('ℜ'),
+            (Variant::Fraktur, 'Z') => Some('ℨ'),
+            (Variant::DoubleStruck, 'C') => Some('ℂ'),
+            (Variant::DoubleStruck, 'H') => Some('ℍ'),
+            (Variant::DoubleStruck, 'N') => Some('ℕ'),
+            (Variant::DoubleStruck, 'P') => Some('ℙ'),
+            (Variant::DoubleStruck, 'Q') => Some('ℚ'),
+            (Variant::DoubleStruck, 'R') => Some('ℝ'),
+            (Variant::DoubleStruck, 'Z') => Some('ℤ'),
+            _ => None,
+        };
+        if let Some(hole) = hole {
+            return hole;
+        }
+        let code = match c {
+            'A'..='Z' => letters + (c as u32 - 'A' as u32),
+            'a'..='z' => letters + 26 + (c as u32 - 'a' as u32),
+            '0'..='9' => match digits {
+                Some(digits) => digits + (c as u32 - '0' as u32),
+                None => return c,
+            },
+            _ => return c,
+        };
+        char::from_u32(code).unwrap_or(c)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AtomKind {
+    Ordinary,
+    /// Big operators and functions such as `\sum` or `\lim`, which take scripts as limits.
+    Limits,
+    /// Like [`AtomKind::Ordinary`], but followed by a function application operator.
+    Function { limits: bool },
+}
+
+struct Atom {
+    mathml: String,
+    kind: AtomKind,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Self {
+        Self {
+            mathml,
+            kind: AtomKind::Ordinary,
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    variant: Option<Variant>,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn next_token(&mut self) -> Token {
+        self.skip_whitespace();
+        let Some(&c) = self.chars.get(self.pos) else {
+            return Token::End;
+        };
+        self.pos += 1;
+        if c != '\\' {
+            return Token::Char(c);
+        }
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            // control symbol such as `\,` or `\{`
+            match self.chars.get(self.pos) {
+                Some(&c) => {
+                    self.pos += 1;
+                    Token::Command(c.to_string())
+                }
+                None => Token::Command(String::new()),
+            }
+        } else {
+            Token::Command(self.chars[start..self.pos].iter().collect())
+        }
+    }
+
+    fn peek_token(&mut self) -> Token {
+        let pos = self.pos;
+        let token = self.next_token();
+        self.pos = pos;
+        token
+    }
+
+    fn expect_char(&mut self, expected: char) -> Result<(), MathError> {
+        match self.next_token() {
+            Token::Char(c) if c == expected => Ok(()),
+            Token::End => Err(MathError::UnexpectedEnd),
+            token => Err(MathError::Unexpected(token.to_string())),
+        }
+    }
+
+    /// Reads a brace-delimited argument verbatim, for `\text` and environment names.
+    fn raw_group(&mut self) -> Result<String, MathError> {
+        self.expect_char('{')?;
+        let mut depth = 0;
+        let mut result = String::new();
+        loop {
+            let Some(&c) = self.chars.get(self.pos) else {
+                return Err(MathError::UnexpectedEnd);
+            };
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    // escaped characters never open or close a group
+                    if let Some(&next) = self.chars.get(self.pos) {
+                        self.pos += 1;
+                        if "{}$%&#_ ".contains(next) {
+                            result.push(next);
+                        } else {
+                            result.push(c);
+                            result.push(next);
+                        }
+                    }
+                    continue;
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(result),
+                '}' => depth -= 1,
+                _ => {}
+            }
+            result.push(c);
+        }
+    }
+
+    /// Reads an optional `[…]` argument verbatim.
+    fn optional_raw(&mut self) -> Option<String> {
+        if self.peek_token() != Token::Char('[') {
+            return None;
+        }
+        self.next_token();
+        let start = self.pos;
+        let end = self.chars[start..].iter().position(|&c| c == ']')? + start;
+        self.pos = end + 1;
+        Some(self.chars[start..end].iter().collect())
+    }
+
+    fn is_row_end(token: &Token) -> bool {
+        match token {
+            Token::End | Token::Char('}') | Token::Char('&') => true,
+            Token::Command(name) => matches!(name.as_str(), "\\" | "cr" | "end" | "right" | "middle"),
+            _ => false,
+        }
+    }
+
+    /// Parses atoms until a token that closes the current group, leaving that token unread.
+    fn parse_row(&mut self) -> Result<String, MathError> {
+        let mut row = String::new();
+        loop {
+            let token = self.peek_token();
+            if Self::is_row_end(&token) {
+                return Ok(row);
+            }
+            match token {
+                Token::Command(name) if name == "displaystyle" || name == "textstyle" => {
+                    self.next_token();
+                    let rest = self.parse_row()?;
+                    let display = name == "displaystyle";
+                    row += &format!("<mstyle displaystyle=\"{display}\"><mrow>{rest}</mrow></mstyle>");
+                    return Ok(row);
+                }
+                Token::Command(name) if name == "color" => {
+                    self.next_token();
+                    let color = self.raw_group()?;
+                    let rest = self.parse_row()?;
+                    row += &format!(
+                        "<mstyle mathcolor=\"{}\"><mrow>{rest}</mrow></mstyle>",
+                        escape(&color)
+                    );
+                    return Ok(row);
+                }
+                _ => {}
+            }
+            let atom = self.parse_atom(false)?;
+            row += &self.parse_scripts(atom)?;
+        }
+    }
+
+    /// Parses a group that must end with `}` (the `{` already consumed).
+    fn parse_group(&mut self) -> Result<String, MathError> {
+        let row = self.parse_row()?;
+        self.expect_char('}')?;
+        Ok(format!("<mrow>{row}</mrow>"))
+    }
+
+    /// A macro argument: a braced group or a single token.
+    fn parse_argument(&mut self) -> Result<String, MathError> {
+        match self.peek_token() {
+            Token::End => Err(MathError::UnexpectedEnd),
+            token if Self::is_row_end(&token) => Err(MathError::Unexpected(token.to_string())),
+            _ => Ok(self.parse_atom(true)?.mathml),
+        }
+    }
+
+    fn parse_scripts(&mut self, mut base: Atom) -> Result<String, MathError> {
+        let mut sup: Option<String> = None;
+        let mut sub: Option<String> = None;
+        let mut primes = String::new();
+        loop {
+            match self.peek_token() {
+                Token::Command(name) if name == "limits" || name == "nolimits" => {
+                    self.next_token();
+                    let limits = name == "limits";
+                    base.kind = match base.kind {
+                        AtomKind::Function { .. } => AtomKind::Function { limits },
+                        _ if limits => AtomKind::Limits,
+                        _ => AtomKind::Ordinary,
+                    };
+                }
+                Token::Char('\'') => {
+                    self.next_token();
+                    primes.push('′');
+                }
+                Token::Char('^') => {
+                    self.next_token();
+                    if sup.is_some() {
+                        return Err(MathError::DoubleScript);
+                    }
+                    sup = Some(self.parse_argument()?);
+                }
+                Token::Char('_') => {
+                    self.next_token();
+                    if sub.is_some() {
+                        return Err(MathError::DoubleScript);
+                    }
+                    sub = Some(self.parse_argument()?);
+                }
+                _ => break,
+            }
+        }
+        if !primes.is_empty() {
+            let primes = format!("<mo>{primes}</mo>");
+            sup = Some(match sup {
+                Some(sup) => format!("<mrow>{primes}{sup}</mrow>"),
+                None => primes,
+            });
+        }
+
+        let limits = matches!(
+            base.kind,
+            AtomKind::Limits | AtomKind::Function { limits: true }
+        );
+        let base_ml = base.mathml;
+        let mut result = match (sub, sup) {
+            (None, None) => base_ml,
+            (Some(sub), None) if limits => format!("<munder>{base_ml}{sub}</munder>"),
+            (None, Some(sup)) if limits => format!("<mover>{base_ml}{sup}</mover>"),
+            (Some(sub), Some(sup)) if limits => {
+                format!("<munderover>{base_ml}{sub}{sup}</munderover>")
+            }
+            (Some(sub), None) => format!("<msub>{base_ml}{sub}</msub>"),
+            (None, Some(sup)) => format!("<msup>{base_ml}{sup}</msup>"),
+            (Some(sub), Some(sup)) => format!("<msubsup>{base_ml}{sub}{sup}</msubsup>"),
+        };
+        if let AtomKind::Function { .. } = base.kind {
+            result += "<mo>&#x2061;</mo>";
+        }
+        Ok(result)
+    }
+
+    fn identifier(&self, c: char) -> String {
+        match self.variant {
+            Some(Variant::Normal) => format!("<mi mathvariant=\"normal\">{}</mi>", escape(&c.to_string())),
+            Some(variant) => format!("<mi>{}</mi>", escape(&variant.map(c).to_string())),
+            None => format!("<mi>{}</mi>", escape(&c.to_string())),
+        }
+    }
+
+    /// Parses a single atom. `single` restricts numbers to one digit, as in `x^23`.
+    fn parse_atom(&mut self, single: bool) -> Result<Atom, MathError> {
+        match self.next_token() {
+            Token::End => Err(MathError::UnexpectedEnd),
+            Token::Char('{') => Ok(Atom::new(self.parse_group()?)),
+            Token::Char(c) if c.is_ascii_digit() || (c == '.' && self.next_is_digit()) => {
+                let mut number = c.to_string();
+                if !single {
+                    while let Some(&c) = self.chars.get(self.pos) {
+                        let decimal = c == '.' && self.chars.get(self.pos + 1).is_some_and(|c| c.is_ascii_digit());
+                        if !c.is_ascii_digit() && !decimal {
+                            break;
+                        }
+                        number.push(c);
+                        self.pos += 1;
+                    }
+                }
+                let number: String = match self.variant {
+                    Some(variant) => number.chars().map(|c| variant.map(c)).collect(),
+                    None => number,
+                };
+                Ok(Atom::new(format!("<mn>{}</mn>", escape(&number))))
+            }
+            Token::Char(c) if c.is_alphabetic() => Ok(Atom::new(self.identifier(c))),
+            Token::Char('~') => Ok(Atom::new("<mtext>&#160;</mtext>".to_string())),
+            Token::Char(c @ ('}' | '&' | '^' | '_' | '#' | '%')) => {
+                Err(MathError::Unexpected(c.to_string()))
+            }
+            Token::Char(c) => {
+                let op = match c {
+                    '-' => '−',
+                    '*' => '∗',
+                    '\'' => '′',
+                    c => c,
+                };
+                Ok(Atom::new(format!("<mo>{}</mo>", escape(&op.to_string()))))
+            }
+            Token::Command(name) => self.parse_command(&name),
+        }
+    }
+
+    fn next_is_digit(&self) -> bool {
+        self.chars.get(self.pos).is_some_and(|c| c.is_ascii_digit())
+    }
+
+    /// Parses the delimiter following `\left`, `\right`, `\big` and friends.
+    fn parse_delimiter(&mut self) -> Result<String, MathError> {
+        let delimiter = match self.next_token() {
+            Token::End => return Err(MathError::UnexpectedEnd),
+            Token::Char('.') => return Ok(String::new()),
+            Token::Char(c) => c.to_string(),
+            Token::Command(name) => match symbol(&name) {
+                Some((_, symbol)) => symbol.to_string(),
+                None => return Err(MathError::UnsupportedCommand(name)),
+            },
+        };
+        Ok(escape(&delimiter))
+    }
+
+    fn with_variant(&mut self, variant: Variant) -> Result<String, MathError> {
+        let previous = self.variant.replace(variant);
+        let argument = self.parse_argument();
+        self.variant = previous;
+        argument
+    }
+
+    fn parse_command(&mut self, name: &str) -> Result<Atom, MathError> {
+        if let Some((kind, symbol)) = symbol(name) {
+            let symbol = escape(symbol);
+            return Ok(match kind {
+                SymbolKind::Identifier => Atom::new(format!("<mi>{symbol}</mi>")),
+                SymbolKind::Upright => Atom::new(format!("<mi mathvariant=\"normal\">{symbol}</mi>")),
+                SymbolKind::Operator => Atom::new(format!("<mo>{symbol}</mo>")),
+                SymbolKind::LargeOperator => Atom {
+                    mathml: format!("<mo largeop=\"true\" movablelimits=\"true\">{symbol}</mo>"),
+                    kind: AtomKind::Limits,
+                },
+                SymbolKind::Integral => Atom::new(format!("<mo largeop=\"true\">{symbol}</mo>")),
+            });
+        }
+        if let Some(limits) = function(name) {
+            return Ok(Atom {
+                mathml: format!("<mi>{name}</mi>"),
+                kind: AtomKind::Function { limits },
+            });
+        }
+        if let Some(width) = space(name) {
+            return Ok(Atom::new(format!("<mspace width=\"{width}\"></mspace>")));
+        }
+        if let Some(variant) = Variant::from_command(name) {
+            if name.starts_with("text") {
+                let text = self.raw_group()?;
+                let text: String = text.chars().map(|c| variant.map(c)).collect();
+                return Ok(Atom::new(format!("<mtext>{}</mtext>", escape(&text))));
+            }
+            return Ok(Atom::new(self.with_variant(variant)?));
+        }
+        if let Some((accent, stretchy)) = accent(name) {
+            let argument = self.parse_argument()?;
+            return Ok(Atom::new(format!(
+                "<mover accent=\"true\">{argument}<mo stretchy=\"{stretchy}\">{accent}</mo></mover>"
+            )));
+        }
+        if let Some(size) = delimiter_size(name) {
+            let delimiter = self.parse_delimiter()?;
+            return Ok(Atom::new(format!(
+                "<mo minsize=\"{size}\" maxsize=\"{size}\">{delimiter}</mo>"
+            )));
+        }
+
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                let fraction = format!("<mfrac>{numerator}{denominator}</mfrac>");
+                match name {
+                    "dfrac" | "cfrac" => format!("<mstyle displaystyle=\"true\">{fraction}</mstyle>"),
+                    "tfrac" => format!("<mstyle displaystyle=\"false\">{fraction}</mstyle>"),
+                    _ => fraction,
+                }
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let top = self.parse_argument()?;
+                let bottom = self.parse_argument()?;
+                format!("<mrow><mo>(</mo><mfrac linethickness=\"0\">{top}{bottom}</mfrac><mo>)</mo></mrow>")
+            }
+            "sqrt" => match self.optional_raw() {
+                Some(index) => {
+                    let index = Parser {
+                        chars: index.chars().collect(),
+                        pos: 0,
+                        variant: self.variant,
+                    }
+                    .parse_row()?;
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{radicand}<mrow>{index}</mrow></mroot>")
+                }
+                None => format!("<msqrt>{}</msqrt>", self.parse_argument()?),
+            },
+            "text" | "textrm" | "textup" | "textnormal" | "mbox" | "hbox" => {
+                let text = self.raw_group()?;
+                format!("<mtext>{}</mtext>", escape(&text))
+            }
+            "operatorname" => {
+                let limits = if self.chars.get(self.pos) == Some(&'*') {
+                    self.pos += 1;
+                    true
+                } else {
+                    false
+                };
+                let name = self.raw_group()?;
+                return Ok(Atom {
+                    mathml: format!("<mi>{}</mi>", escape(&name)),
+                    kind: AtomKind::Function { limits },
+                });
+            }
+            "overline" => format!(
+                "<mover accent=\"true\">{}<mo stretchy=\"true\">‾</mo></mover>",
+                self.parse_argument()?
+            ),
+            "underline" => format!(
+                "<munder accentunder=\"true\">{}<mo stretchy=\"true\">_</mo></munder>",
+                self.parse_argument()?
+            ),
+            "overbrace" => {
+                return Ok(Atom {
+                    mathml: format!(
+                        "<mover accent=\"true\">{}<mo stretchy=\"true\">⏞</mo></mover>",
+                        self.parse_argument()?
+                    ),
+                    kind: AtomKind::Limits,
+                })
+            }
+            "underbrace" => {
+                return Ok(Atom {
+                    mathml: format!(
+                        "<munder accentunder=\"true\">{}<mo stretchy=\"true\">⏟</mo></munder>",
+                        self.parse_argument()?
+                    ),
+                    kind: AtomKind::Limits,
+                })
+            }
+            "overset" | "stackrel" => {
+                let over = self.parse_argument()?;
+                let base = self.parse_argument()?;
+                format!("<mover>{base}{over}</mover>")
+            }
+            "underset" => {
+                let under = self.parse_argument()?;
+                let base = self.parse_argument()?;
+                format!("<munder>{base}{under}</munder>")
+            }
+            "boxed" => format!("<menclose notation=\"box\">{}</menclose>", self.parse_argument()?),
+            "cancel" => format!(
+                "<menclose notation=\"updiagonalstrike\">{}</menclose>",
+                self.parse_argument()?
+            ),
+            "phantom" => format!("<mphantom>{}</mphantom>", self.parse_argument()?),
+            "textcolor" => {
+                let color = self.raw_group()?;
+                let argument = self.parse_argument()?;
+                format!("<mstyle mathcolor=\"{}\">{argument}</mstyle>", escape(&color))
+            }
+            "not" => {
+                let next = self.parse_atom(true)?.mathml;
+                // combining long solidus overlay on the negated operator
+                match next.strip_suffix("</mo>") {
+                    Some(op) => format!("{op}\u{0338}</mo>"),
+                    None => format!("<menclose notation=\"updiagonalstrike\">{next}</menclose>"),
+                }
+            }
+            "bmod" | "mod" => "<mo lspace=\"0.2222em\" rspace=\"0.2222em\">mod</mo>".to_string(),
+            "pmod" => format!(
+                "<mspace width=\"1em\"></mspace><mo>(</mo><mi>mod</mi><mspace width=\"0.3333em\"></mspace>{}<mo>)</mo>",
+                self.parse_argument()?
+            ),
+            "left" => {
+                let open = self.parse_delimiter()?;
+                let mut inner = self.parse_row()?;
+                loop {
+                    match self.next_token() {
+                        Token::Command(name) if name == "middle" => {
+                            let middle = self.parse_delimiter()?;
+                            inner += &format!("<mo stretchy=\"true\">{middle}</mo>");
+                            inner += &self.parse_row()?;
+                        }
+                        Token::Command(name) if name == "right" => break,
+                        Token::End => return Err(MathError::UnexpectedEnd),
+                        token => return Err(MathError::Unexpected(token.to_string())),
+                    }
+                }
+                let close = self.parse_delimiter()?;
+                format!(
+                    "<mrow><mo fence=\"true\" stretchy=\"true\">{open}</mo>{inner}<mo fence=\"true\" stretchy=\"true\">{close}</mo></mrow>"
+                )
+            }
+            "begin" => self.parse_environment()?,
+            "right" | "middle" | "end" | "\\" | "cr" => {
+                return Err(MathError::Unexpected(format!("\\{name}")))
+            }
+            _ => return Err(MathError::UnsupportedCommand(name.to_string())),
+        };
+        Ok(Atom::new(mathml))
+    }
+
+    fn parse_environment(&mut self) -> Result<String, MathError> {
+        let name = self.raw_group()?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("&#123;", "&#125;", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("&#123;", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" | "alignat" | "alignat*" => {
+                ("", "", Some("right left"))
+            }
+            "gathered" | "gather" | "gather*" => ("", "", Some("center")),
+            "array" => {
+                // column spec is only used for alignment
+                let spec = self.raw_group()?;
+                let align = spec
+                    .chars()
+                    .filter_map(|c| match c {
+                        'l' => Some("left"),
+                        'c' => Some("center"),
+                        'r' => Some("right"),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                return self.parse_table(&name, "", "", &align);
+            }
+            _ => return Err(MathError::UnsupportedEnvironment(name)),
+        };
+        if name.starts_with("alignat") {
+            // column count argument
+            self.raw_group()?;
+        }
+        self.parse_table(&name, open, close, align.unwrap_or("center"))
+    }
+
+    fn parse_table(
+        &mut self,
+        name: &str,
+        open: &str,
+        close: &str,
+        align: &str,
+    ) -> Result<String, MathError> {
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            cells.push(format!("<mtd>{}</mtd>", self.parse_row()?));
+            match self.next_token() {
+                Token::Char('&') => {}
+                Token::Command(command) if command == "\\" || command == "cr" => {
+                    // optional vertical space such as `\\[2pt]`
+                    self.optional_raw();
+                    rows.push(std::mem::take(&mut cells));
+                }
+                Token::Command(command) if command == "end" => {
+                    let end = self.raw_group()?;
+                    if end != name {
+                        return Err(MathError::Unexpected(format!("\\end{{{end}}}")));
+                    }
+                    break;
+                }
+                Token::End => return Err(MathError::UnexpectedEnd),
+                token => return Err(MathError::Unexpected(token.to_string())),
+            }
+        }
+        // a trailing `\\` leaves an empty last row
+        if !(cells.len() == 1 && cells[0] == "<mtd></mtd>") {
+            rows.push(cells);
+        }
+        let rows: String = rows
+            .into_iter()
+            .map(|cells| format!("<mtr>{}</mtr>", cells.concat()))
+            .collect();
+        let table = format!("<mtable columnalign=\"{align}\">{rows}</mtable>");
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+        let fence = |delimiter: &str| {
+            if delimiter.is_empty() {
+                String::new()
+            } else {
+                format!("<mo fence=\"true\" stretchy=\"true\">{delimiter}</mo>")
+            }
+        };
+        Ok(format!("<mrow>{}{table}{}</mrow>", fence(open), fence(close)))
+    }
+}
+
+enum SymbolKind {
+    Identifier,
+    /// Identifiers that stay upright, like uppercase greek letters.
+    Upright,
+    Operator,
+    LargeOperator,
+    Integral,
+}
+
+fn symbol(name: &str) -> Option<(SymbolKind, &'static str)> {
+    use SymbolKind::*;
+    Some(match name {
+        "alpha" => (Identifier, "α"),
+        "beta" => (Identifier, "β"),
+        "gamma" => (Identifier, "γ"),
+        "delta" => (Identifier, "δ"),
+        "epsilon" => (Identifier, "ϵ"),
+        "varepsilon" => (Identifier, "ε"),
+        "zeta" => (Identifier, "ζ"),
+        "eta" => (Identifier, "η"),
+        "theta" => (Identifier, "θ"),
+        "vartheta" => (Identifier, "ϑ"),
+        "iota" => (Identifier, "ι"),
+        "kappa" => (Identifier, "κ"),
+        "lambda" => (Identifier, "λ"),
+        "mu" => (Identifier, "μ"),
+        "nu" => (Identifier, "ν"),
+        "xi" => (Identifier, "ξ"),
+        "omicron" => (Identifier, "ο"),
+        "pi" => (Identifier, "π"),
+        "varpi" => (Identifier, "ϖ"),
+        "rho" => (Identifier, "ρ"),
+        "varrho" => (Identifier, "ϱ"),
+        "sigma" => (Identifier, "σ"),
+        "varsigma" => (Identifier, "ς"),
+        "tau" => (Identifier, "τ"),
+        "upsilon" => (Identifier, "υ"),
+        "phi" => (Identifier, "ϕ"),
+        "varphi" => (Identifier, "φ"),
+        "chi" => (Identifier, "χ"),
+        "psi" => (Identifier, "ψ"),
+        "omega" => (Identifier, "ω"),
+        "Gamma" => (Upright, "Γ"),
+        "Delta" => (Upright, "Δ"),
+        "Theta" => (Upright, "Θ"),
+        "Lambda" => (Upright, "Λ"),
+        "Xi" => (Upright, "Ξ"),
+        "Pi" => (Upright, "Π"),
+        "Sigma" => (Upright, "Σ"),
+        "Upsilon" => (Upright, "Υ"),
+        "Phi" => (Upright, "Φ"),
+        "Psi" => (Upright, "Ψ"),
+        "Omega" => (Upright, "Ω"),
+
+        "infty" => (Identifier, "∞"),
+        "partial" => (Identifier, "∂"),
+        "nabla" => (Identifier, "∇"),
+        "emptyset" | "varnothing" => (Identifier, "∅"),
+        "hbar" => (Identifier, "ℏ"),
+        "ell" => (Identifier, "ℓ"),
+        "Re" => (Identifier, "ℜ"),
+        "Im" => (Identifier, "ℑ"),
+        "aleph" => (Identifier, "ℵ"),
+        "wp" => (Identifier, "℘"),
+        "imath" => (Identifier, "ı"),
+        "jmath" => (Identifier, "ȷ"),
+        "angle" => (Identifier, "∠"),
+        "triangle" => (Identifier, "△"),
+        "top" => (Identifier, "⊤"),
+        "bot" => (Identifier, "⊥"),
+        "dagger" => (Identifier, "†"),
+        "ddagger" => (Identifier, "‡"),
+        "degree" => (Identifier, "°"),
+
+        "times" => (Operator, "×"),
+        "cdot" | "cdotp" => (Operator, "⋅"),
+        "pm" => (Operator, "±"),
+        "mp" => (Operator, "∓"),
+        "div" => (Operator, "÷"),
+        "ast" => (Operator, "∗"),
+        "star" => (Operator, "⋆"),
+        "circ" => (Operator, "∘"),
+        "bullet" => (Operator, "∙"),
+        "oplus" => (Operator, "⊕"),
+        "ominus" => (Operator, "⊖"),
+        "otimes" => (Operator, "⊗"),
+        "odot" => (Operator, "⊙"),
+        "cup" => (Operator, "∪"),
+        "cap" => (Operator, "∩"),
+        "sqcup" => (Operator, "⊔"),
+        "sqcap" => (Operator, "⊓"),
+        "uplus" => (Operator, "⊎"),
+        "setminus" => (Operator, "∖"),
+        "wedge" | "land" => (Operator, "∧"),
+        "vee" | "lor" => (Operator, "∨"),
+        "neg" | "lnot" => (Operator, "¬"),
+        "leq" | "le" => (Operator, "≤"),
+        "geq" | "ge" => (Operator, "≥"),
+        "neq" | "ne" => (Operator, "≠"),
+        "ll" => (Operator, "≪"),
+        "gg" => (Operator, "≫"),
+        "approx" => (Operator, "≈"),
+        "equiv" => (Operator, "≡"),
+        "sim" => (Operator, "∼"),
+        "simeq" => (Operator, "≃"),
+        "cong" => (Operator, "≅"),
+        "propto" => (Operator, "∝"),
+        "prec" => (Operator, "≺"),
+        "succ" => (Operator, "≻"),
+        "preceq" => (Operator, "⪯"),
+        "succeq" => (Operator, "⪰"),
+        "in" => (Operator, "∈"),
+        "notin" => (Operator, "∉"),
+        "ni" => (Operator, "∋"),
+        "subset" => (Operator, "⊂"),
+        "supset" => (Operator, "⊃"),
+        "subseteq" => (Operator, "⊆"),
+        "supseteq" => (Operator, "⊇"),
+        "subsetneq" => (Operator, "⊊"),
+        "supsetneq" => (Operator, "⊋"),
+        "perp" => (Operator, "⊥"),
+        "parallel" => (Operator, "∥"),
+        "mid" => (Operator, "∣"),
+        "models" => (Operator, "⊨"),
+        "vdash" => (Operator, "⊢"),
+        "forall" => (Operator, "∀"),
+        "exists" => (Operator, "∃"),
+        "nexists" => (Operator, "∄"),
+        "therefore" => (Operator, "∴"),
+        "because" => (Operator, "∵"),
+        "to" | "rightarrow" => (Operator, "→"),
+        "gets" | "leftarrow" => (Operator, "←"),
+        "leftrightarrow" => (Operator, "↔"),
+        "Rightarrow" => (Operator, "⇒"),
+        "Leftarrow" => (Operator, "⇐"),
+        "Leftrightarrow" => (Operator, "⇔"),
+        "implies" | "Longrightarrow" => (Operator, "⟹"),
+        "impliedby" | "Longleftarrow" => (Operator, "⟸"),
+        "iff" | "Longleftrightarrow" => (Operator, "⟺"),
+        "longrightarrow" => (Operator, "⟶"),
+        "longleftarrow" => (Operator, "⟵"),
+        "mapsto" => (Operator, "↦"),
+        "uparrow" => (Operator, "↑"),
+        "downarrow" => (Operator, "↓"),
+        "Uparrow" => (Operator, "⇑"),
+        "Downarrow" => (Operator, "⇓"),
+        "ldots" | "dots" | "dotsc" => (Operator, "…"),
+        "cdots" | "dotsb" => (Operator, "⋯"),
+        "vdots" => (Operator, "⋮"),
+        "ddots" => (Operator, "⋱"),
+        "prime" => (Operator, "′"),
+        "colon" => (Operator, ":"),
+        "langle" => (Operator, "⟨"),
+        "rangle" => (Operator, "⟩"),
+        "lfloor" => (Operator, "⌊"),
+        "rfloor" => (Operator, "⌋"),
+        "lceil" => (Operator, "⌈"),
+        "rceil" => (Operator, "⌉"),
+        "vert" | "lvert" | "rvert" => (Operator, "|"),
+        "Vert" | "lVert" | "rVert" | "|" => (Operator, "‖"),
+        "backslash" => (Operator, "\\"),
+        "{" | "lbrace" => (Operator, "{"),
+        "}" | "rbrace" => (Operator, "}"),
+        "%" => (Operator, "%"),
+        "$" => (Operator, "$"),
+        "#" => (Operator, "#"),
+        "&" => (Operator, "&"),
+        "_" => (Operator, "_"),
+
+        "sum" => (LargeOperator, "∑"),
+        "prod" => (LargeOperator, "∏"),
+        "coprod" => (LargeOperator, "∐"),
+        "bigcup" => (LargeOperator, "⋃"),
+        "bigcap" => (LargeOperator, "⋂"),
+        "bigoplus" => (LargeOperator, "⨁"),
+        "bigotimes" => (LargeOperator, "⨂"),
+        "bigodot" => (LargeOperator, "⨀"),
+        "biguplus" => (LargeOperator, "⨄"),
+        "bigsqcup" => (LargeOperator, "⨆"),
+        "bigvee" => (LargeOperator, "⋁"),
+        "bigwedge" => (LargeOperator, "⋀"),
+        "int" => (Integral, "∫"),
+        "iint" => (Integral, "∬"),
+        "iiint" => (Integral, "∭"),
+        "oint" => (Integral, "∮"),
+        _ => return None,
+    })
+}
+
+/// Named functions; `true` if they take their scripts as limits like `\lim`.
+fn function(name: &str) -> Option<bool> {
+    Some(match name {
+        "sin" | "cos" | "tan" | "cot" | "sec" | "csc" | "arcsin" | "arccos" | "arctan" | "sinh"
+        | "cosh" | "tanh" | "coth" | "log" | "ln" | "lg" | "exp" | "dim" | "ker" | "deg"
+        | "arg" | "hom" => false,
+        "lim" | "liminf" | "limsup" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" => true,
+        _ => return None,
+    })
+}
+
+fn space(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "," | "thinspace" => "0.1667em",
+        ":" | ">" | "medspace" => "0.2222em",
+        ";" | "thickspace" => "0.2778em",
+        "!" | "negthinspace" => "-0.1667em",
+        " " | "space" => "0.3333em",
+        "enspace" => "0.5em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        _ => return None,
+    })
+}
+
+/// Accent character and whether it stretches over its base.
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "hat" => ("^", false),
+        "widehat" => ("^", true),
+        "tilde" => ("~", false),
+        "widetilde" => ("~", true),
+        "bar" => ("¯", false),
+        "vec" => ("→", false),
+        "overrightarrow" => ("→", true),
+        "overleftarrow" => ("←", true),
+        "dot" => ("˙", false),
+        "ddot" => ("¨", false),
+        "acute" => ("´", false),
+        "grave" => ("&#96;", false),
+        "check" => ("ˇ", false),
+        "breve" => ("˘", false),
+        _ => return None,
+    })
+}
+
+fn delimiter_size(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "big" | "bigl" | "bigr" | "bigm" => "1.2em",
+        "Big" | "Bigl" | "Bigr" | "Bigm" => "1.8em",
+        "bigg" | "biggl" | "biggr" | "biggm" => "2.4em",
+        "Bigg" | "Biggl" | "Biggr" | "Biggm" => "3em",
+        _ => return None,
+    })
+}
@@ -0,0 +1,465 @@
+//! Svelte mustache tags (`{expr}`, `{#if}`, `{:else}`, `{/if}`, `{@const}`, …) in markdown.
+//!
+//! Markdown would mangle expressions such as `{a * b * c}`, so every tag is replaced by a
+//! placeholder of the same length before parsing and restored while rendering. Keeping the
+//! length keeps all node positions valid.
+//!
+//! The same scan finds `<script>` and `<style>` elements (see [`elements`]), which hold JS and
+//! CSS instead of tags and are taken out of the markdown altogether.
+
+use std::collections::BTreeMap;
+
+use markdown::unist::Position;
+
+/// Starts placeholders. A C1 control character, which neither escapes nor character
+/// references can produce (markdown-rs decodes those to U+FFFD), so text can't pass for a
+/// placeholder. The parser still treats it as plain text, link destinations included.
+const MARKER: char = '\u{80}';
+
+/// Fills the rest of placeholders, a letter for the same reason.
+const FILLER: u8 = b'x';
+
+/// Mustache tags of one document, keyed by their start offset in the source.
+#[derive(Debug, Default)]
+pub struct Tags {
+    tags: BTreeMap<usize, Tag>,
+}
+
+#[derive(Debug)]
+struct Tag {
+    source: String,
+    placeholder: String,
+}
+
+/// Part of a string with placeholders in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Text(String),
+    /// The original source of a mustache tag, including braces.
+    Tag(String),
+}
+
+impl Tags {
+    /// Finds mustache tags in `input` and returns the input with every tag masked.
+    pub fn mask(input: &str) -> (String, Tags) {
+        let (ranges, _) = find(input);
+        let mut masked = input.as_bytes().to_vec();
+        let mut tags = BTreeMap::new();
+        for (start, end) in ranges {
+            let source = input[start..end].to_string();
+            // tags with only one character per line have no room for the marker, they stay text
+            let Some(placeholder) = placeholder(&source) else {
+                continue;
+            };
+            masked[start..end].copy_from_slice(placeholder.as_bytes());
+            tags.insert(start, Tag { source, placeholder });
+        }
+        // every byte of multi-byte characters is replaced, so the result is valid UTF-8
+        let masked = String::from_utf8(masked).expect("masking keeps UTF-8 valid");
+        (masked, Tags { tags })
+    }
+
+    /// Splits strings taken (in source order) from a node at `pos` into text and tags.
+    ///
+    /// Placeholders are matched to tags by order, so `values` has to contain every string of
+    /// the node that can hold a placeholder, in the order they appear in the source.
+    pub fn split(&self, values: &[&str], pos: Option<&Position>) -> Vec<Vec<Segment>> {
+        let mut tags = match pos {
+            Some(pos) => self.tags.range(pos.start.offset..pos.end.offset),
+            None => self.tags.range(0..0),
+        }
+        .map(|(_, tag)| tag)
+        .peekable();
+
+        values
+            .iter()
+            .map(|value| {
+                let mut segments = Vec::new();
+                let mut text = String::new();
+                let mut rest = *value;
+                while let Some(marker) = rest.find(MARKER) {
+                    // a literal marker character that isn't part of the next tag stays as is
+                    let start = tags.peek().and_then(|tag| {
+                        let start = marker.checked_sub(tag.placeholder.find(MARKER)?)?;
+                        rest[start..].starts_with(&tag.placeholder).then_some(start)
+                    });
+                    let Some(start) = start else {
+                        let end = marker + MARKER.len_utf8();
+                        text += &rest[..end];
+                        rest = &rest[end..];
+                        continue;
+                    };
+                    let tag = tags.next().expect("peeked above");
+                    text += &rest[..start];
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Tag(tag.source.clone()));
+                    rest = &rest[start + tag.placeholder.len()..];
+                }
+                text += rest;
+                if !text.is_empty() {
+                    segments.push(Segment::Text(text));
+                }
+                segments
+            })
+            .collect()
+    }
+
+    /// Like [`Tags::split`], but puts the original tag source back as plain text.
+    pub fn restore(&self, values: &[&str], pos: Option<&Position>) -> Vec<String> {
+        if self.tags.is_empty() {
+            return values.iter().map(|v| v.to_string()).collect();
+        }
+        self.split(values, pos)
+            .into_iter()
+            .map(|segments| {
+                segments
+                    .into_iter()
+                    .map(|segment| match segment {
+                        Segment::Text(text) | Segment::Tag(text) => text,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Whether a tag is a block tag (`{#if}`, `{:else}`, `{/if}`) or a special tag like `{@const}`,
+/// which can't be wrapped in a paragraph.
+pub fn is_block_tag(tag: &str) -> bool {
+    matches!(tag.as_bytes().get(1), Some(b'#' | b':' | b'/' | b'@'))
+}
+
+/// Byte ranges of the closed `<script>` and `<style>` elements in a markdown document, outside
+/// of code.
+pub fn elements(input: &str) -> Vec<(usize, usize)> {
+    find(input).1
+}
+
+/// Placeholder of the same length as `tag`: the marker on the first two bytes that aren't line
+/// endings, the filler on all others, so lines stay where they are.
+fn placeholder(tag: &str) -> Option<String> {
+    let mut bytes = tag
+        .bytes()
+        .map(|b| if matches!(b, b'\n' | b'\r') { b } else { FILLER })
+        .collect::<Vec<_>>();
+    let marker = bytes.windows(2).position(|pair| pair == [FILLER, FILLER])?;
+    MARKER.encode_utf8(&mut bytes[marker..marker + 2]);
+    Some(String::from_utf8(bytes).expect("the marker is a whole character"))
+}
+
+/// Start and end offset in bytes.
+type Range = (usize, usize);
+
+/// Byte ranges of the mustache tags and of the `<script>`/`<style>` elements in a markdown
+/// document.
+///
+/// Frontmatter, fenced and indented code, code spans, math, comments and elements are skipped
+/// for tags, as are escaped braces (`\{`) and empty ones (`{}`).
+fn find(input: &str) -> (Vec<Range>, Vec<Range>) {
+    let bytes = input.as_bytes();
+    let mut ranges = Vec::new();
+    let mut elements = Vec::new();
+    let mut index = skip_frontmatter(input);
+    let mut fence: Option<(u8, usize)> = None;
+    let mut blocks = Blocks::default();
+    let mut line_start = true;
+
+    while index < bytes.len() {
+        if line_start {
+            line_start = false;
+            let line_end = input[index..].find('\n').map_or(bytes.len(), |i| index + i + 1);
+            let full_line = input[index..line_end].trim_end_matches(['\n', '\r']);
+            let line = input[index..line_end].trim_start_matches([' ', '\t', '>']);
+            let marker = line.bytes().next().unwrap_or_default();
+            let run = line.bytes().take_while(|b| *b == marker).count();
+            match fence {
+                Some((fence_marker, fence_len)) => {
+                    if marker == fence_marker && run >= fence_len && line[run..].trim().is_empty() {
+                        fence = None;
+                    }
+                    index = line_end;
+                    line_start = true;
+                    continue;
+                }
+                None if blocks.indented_code(full_line) => {
+                    index = line_end;
+                    line_start = true;
+                    continue;
+                }
+                None if matches!(marker, b'`' | b'~') && run >= 3 => {
+                    fence = Some((marker, run));
+                    index = line_end;
+                    line_start = true;
+                    continue;
+                }
+                None if marker == b'$' && run >= 2 && !line[run..].contains("$$") => {
+                    fence = Some((marker, run));
+                    index = line_end;
+                    line_start = true;
+                    continue;
+                }
+                None => {}
+            }
+        }
+
+        match bytes[index] {
+            b'\n' => {
+                line_start = true;
+                index += 1;
+            }
+            // an escaped line ending is a hard break, the next line still has to be checked
+            b'\\' if bytes.get(index + 1) != Some(&b'\n') => index += 2,
+            marker @ (b'`' | b'$') => {
+                let run = bytes[index..].iter().take_while(|b| **b == marker).count();
+                index += run;
+                if let Some(end) = closing_run(bytes, index, marker, run) {
+                    index = end;
+                }
+            }
+            b'<' => {
+                let rest = &input[index..];
+                if let Some(close) = element_close(rest) {
+                    match rest.to_ascii_lowercase().find(close) {
+                        Some(end) => {
+                            elements.push((index, index + end + close.len()));
+                            index += end + close.len();
+                        }
+                        None => index = bytes.len(),
+                    }
+                } else if rest.starts_with("<!--") {
+                    index += rest.find("-->").map_or(rest.len(), |end| end + 3);
+                } else {
+                    index += 1;
+                }
+            }
+            b'{' => match tag_end(bytes, index) {
+                Some(end) => {
+                    ranges.push((index, end));
+                    index = end;
+                }
+                None => index += 1,
+            },
+            _ => index += 1,
+        }
+    }
+    (ranges, elements)
+}
+
+/// Just enough block structure to recognize indented code: the content indentation of open list
+/// items, and whether the previous line could be continued as a paragraph.
+#[derive(Default)]
+struct Blocks {
+    items: Vec<usize>,
+    paragraph: bool,
+}
+
+impl Blocks {
+    /// Whether `line` is indented code, moving on to the next line.
+    fn indented_code(&mut self, line: &str) -> bool {
+        let (indent, content) = indentation(line);
+        if content.trim().is_empty() {
+            self.paragraph = false;
+            return false;
+        }
+        if let Some(width) = list_marker(content) {
+            // a new item closes the items it isn't nested in
+            while self.items.last().is_some_and(|item| *item > indent) {
+                self.items.pop();
+            }
+            if indent < self.items.last().copied().unwrap_or(0) + 4 {
+                self.items.push(indent + width);
+                self.paragraph = true;
+                return false;
+            }
+        } else if !self.paragraph {
+            // past a blank line, lines indented less than an item's content end it
+            while self.items.last().is_some_and(|item| indent < *item) {
+                self.items.pop();
+            }
+        }
+        // indented code can't interrupt a paragraph, the line continues it instead
+        if !self.paragraph && indent >= self.items.last().copied().unwrap_or(0) + 4 {
+            return true;
+        }
+        self.paragraph = !matches!(content.as_bytes()[0], b'#' | b'`' | b'~');
+        false
+    }
+}
+
+/// Width of the indentation of `line` after blockquote markers, and the rest of the line.
+fn indentation(line: &str) -> (usize, &str) {
+    let mut rest = line;
+    loop {
+        let trimmed = rest.trim_start_matches(' ');
+        match trimmed.strip_prefix('>') {
+            Some(quoted) if rest.len() - trimmed.len() < 4 => {
+                rest = quoted.strip_prefix(' ').unwrap_or(quoted);
+            }
+            _ => break,
+        }
+    }
+    let mut width = 0;
+    let content = rest.trim_start_matches(|c| match c {
+        ' ' => {
+            width += 1;
+            true
+        }
+        '\t' => {
+            width += 4 - width % 4;
+            true
+        }
+        _ => false,
+    });
+    (width, content)
+}
+
+/// Width of the list item marker starting `content` with the spaces after it, if it starts
+/// with one.
+fn list_marker(content: &str) -> Option<usize> {
+    let bytes = content.as_bytes();
+    let marker = match bytes[0] {
+        b'-' | b'+' | b'*' => 1,
+        _ => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            if !(1..=9).contains(&digits) || !matches!(bytes.get(digits), Some(b'.' | b')')) {
+                return None;
+            }
+            digits + 1
+        }
+    };
+    let spaces = bytes[marker..].iter().take_while(|b| **b == b' ').count();
+    match spaces {
+        _ if marker + spaces == bytes.len() => Some(marker + 1),
+        0 => None,
+        // with more spaces the content is indented code, one space belongs to the marker
+        1..=4 => Some(marker + spaces),
+        _ => Some(marker + 1),
+    }
+}
+
+/// Closing tag of the `<script>` or `<style>` element starting `rest`, if it starts with one.
+fn element_close(rest: &str) -> Option<&'static str> {
+    let (name, close) = [("script", "</script>"), ("style", "</style>")]
+        .into_iter()
+        .find(|(name, _)| {
+            rest.get(1..name.len() + 1)
+                .is_some_and(|tag| tag.eq_ignore_ascii_case(name))
+        })?;
+    // `<scripts>` isn't a script
+    match rest.as_bytes().get(name.len() + 1) {
+        Some(b'>' | b'/') => Some(close),
+        Some(b) if b.is_ascii_whitespace() => Some(close),
+        _ => None,
+    }
+}
+
+/// Length of YAML or TOML frontmatter at the start of the document, including its fences.
+fn skip_frontmatter(input: &str) -> usize {
+    let Some(fence) = ["---", "+++"].into_iter().find(|f| input.starts_with(f)) else {
+        return 0;
+    };
+    let mut lines = input.split_inclusive('\n');
+    let first = lines.next().unwrap_or_default();
+    if first.trim_end() != fence {
+        return 0;
+    }
+    let mut end = first.len();
+    for line in lines {
+        end += line.len();
+        if line.trim_end() == fence {
+            return end;
+        }
+    }
+    0
+}
+
+/// End of a code span or inline math closing a run of `run` markers, if there is one.
+fn closing_run(bytes: &[u8], mut index: usize, marker: u8, run: usize) -> Option<usize> {
+    while index < bytes.len() {
+        if bytes[index] == marker {
+            let len = bytes[index..].iter().take_while(|b| **b == marker).count();
+            if len == run {
+                return Some(index + len);
+            }
+            index += len;
+        } else if bytes[index] == b'\n' && is_blank_line(bytes, index + 1) {
+            // spans don't continue past the end of a paragraph
+            return None;
+        } else {
+            index += 1;
+        }
+    }
+    None
+}
+
+fn is_blank_line(bytes: &[u8], start: usize) -> bool {
+    bytes[start..]
+        .iter()
+        .take_while(|b| **b != b'\n')
+        .all(|b| matches!(b, b' ' | b'\t' | b'\r'))
+}
+
+/// End (exclusive) of the mustache tag starting at `start`, skipping braces in JS strings.
+fn tag_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut index = start;
+    // brace depth at which each open template literal's `${` started
+    let mut templates = Vec::new();
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'\n' if is_blank_line(bytes, index + 1) => return None,
+            quote @ (b'"' | b'\'') => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != quote {
+                    match bytes[index] {
+                        b'\\' => index += 1,
+                        b'\n' => return None,
+                        _ => {}
+                    }
+                    index += 1;
+                }
+            }
+            b'`' => index = template(bytes, index + 1, &mut depth, &mut templates),
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if templates.last() == Some(&depth) {
+                    templates.pop();
+                    index = template(bytes, index + 1, &mut depth, &mut templates);
+                } else if depth == 0 {
+                    let empty = bytes[start + 1..index].iter().all(u8::is_ascii_whitespace);
+                    return (!empty).then_some(index + 1);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Skips template literal text from `index` to its closing backtick or to the `{` of its next
+/// `${`, returning the index of either.
+fn template(
+    bytes: &[u8],
+    mut index: usize,
+    depth: &mut usize,
+    templates: &mut Vec<usize>,
+) -> usize {
+    while index < bytes.len() && bytes[index] != b'`' {
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'$' if bytes.get(index + 1) == Some(&b'{') => {
+                templates.push(*depth);
+                *depth += 1;
+                return index + 1;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    index
+}
@@ -0,0 +1,259 @@
+//! Sanitizing for [`MdsvexrsOptions::safe`](crate::MdsvexrsOptions::safe), for markdown written
+//! by untrusted users.
+//!
+//! Raw HTML keeps allowed elements and attributes only, everything else is dropped (the text
+//! inside unknown elements stays, the content of scripts, styles and the like doesn't). URLs
+//! with a protocol not in the allowlists are emptied, like markdown-rs does without
+//! `allow_dangerous_protocol`.
+
+use crate::{escape_braces, text_encode, url_encode};
+
+/// Elements kept in raw HTML, as on GitHub.
+pub const SAFE_TAGS: &[&str] = &[
+    "a", "abbr", "b", "bdi", "bdo", "blockquote", "br", "caption", "cite", "code", "col",
+    "colgroup", "dd", "del", "details", "dfn", "div", "dl", "dt", "em", "figcaption", "figure",
+    "h1", "h2", "h3", "h4", "h5", "h6", "hr", "i", "img", "ins", "kbd", "li", "mark", "ol", "p",
+    "picture", "pre", "q", "rp", "rt", "ruby", "s", "samp", "small", "source", "span", "strike",
+    "strong", "sub", "summary", "sup", "table", "tbody", "td", "tfoot", "th", "thead", "time",
+    "tr", "tt", "u", "ul", "var", "wbr",
+];
+
+/// Attributes kept on allowed elements. Event handlers and `style` never are.
+pub const SAFE_ATTRIBUTES: &[&str] = &[
+    "abbr", "align", "alt", "cite", "class", "colspan", "datetime", "dir", "height", "href",
+    "hidden", "id", "lang", "open", "reversed", "rowspan", "span", "src", "srcset", "start",
+    "summary", "title", "type", "valign", "width",
+];
+
+/// Protocols allowed in links (`href`, `cite`), URLs without one are relative and allowed.
+pub const SAFE_HREF_PROTOCOLS: &[&str] = &["http", "https", "mailto", "irc", "ircs", "xmpp"];
+
+/// Protocols allowed in image sources (`src`, `srcset`).
+pub const SAFE_SRC_PROTOCOLS: &[&str] = &["http", "https"];
+
+/// Elements dropped together with their content.
+const DROPPED_CONTENT: &[&str] = &[
+    "script", "style", "textarea", "title", "iframe", "noembed", "noframes", "noscript",
+    "plaintext", "template", "xmp",
+];
+
+/// Whether `url` is relative or uses one of `protocols`.
+pub fn url_allowed(url: &str, protocols: &[&str]) -> bool {
+    // a colon only starts a protocol before any path, query or fragment
+    let Some(end) = url.find([':', '/', '?', '#']) else {
+        return true;
+    };
+    if url.as_bytes()[end] != b':' {
+        return true;
+    }
+    let protocol = url[..end].to_ascii_lowercase();
+    protocols.contains(&protocol.as_str())
+}
+
+/// Raw HTML reduced to allowed elements and attributes, with braces escaped.
+pub fn html(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('<') {
+        result += &text(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(end) = rest.strip_prefix("<!--").map(|comment| comment.find("-->")) {
+            rest = end.map_or("", |end| &rest[4 + end + 3..]);
+            continue;
+        }
+        let Some(tag) = Tag::parse(rest) else {
+            if rest.starts_with("<!") || rest.starts_with("<?") {
+                // doctypes, CDATA and processing instructions
+                rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            } else {
+                result += "&lt;";
+                rest = &rest[1..];
+            }
+            continue;
+        };
+        rest = &rest[tag.len..];
+        if !tag.closing && DROPPED_CONTENT.contains(&tag.name.as_str()) {
+            let close = format!("</{}", tag.name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(end) => rest[end..].find('>').map_or("", |gt| &rest[end + gt + 1..]),
+                None => "",
+            };
+            continue;
+        }
+        if SAFE_TAGS.contains(&tag.name.as_str()) {
+            result += &tag.html();
+        }
+    }
+    result += &text(rest);
+    result
+}
+
+/// Text between tags. It's HTML already, so entities are kept.
+fn text(value: &str) -> String {
+    escape_braces(&value.replace('>', "&gt;"))
+}
+
+/// Start or end tag in raw HTML.
+struct Tag {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, Option<String>)>,
+    self_closing: bool,
+    /// Length of the tag's source.
+    len: usize,
+}
+
+impl Tag {
+    /// Parses the tag at the start of `value`, which starts with `<`.
+    fn parse(value: &str) -> Option<Tag> {
+        let bytes = value.as_bytes();
+        let closing = bytes.get(1) == Some(&b'/');
+        let mut index = if closing { 2 } else { 1 };
+        if !bytes.get(index)?.is_ascii_alphabetic() {
+            return None;
+        }
+        let name_end = index + bytes[index..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric() || **b == b'-')
+            .count();
+        let name = value[index..name_end].to_ascii_lowercase();
+        index = name_end;
+
+        let mut attributes = Vec::new();
+        let mut self_closing = false;
+        loop {
+            while bytes.get(index)?.is_ascii_whitespace() {
+                index += 1;
+            }
+            match bytes[index] {
+                b'>' => break,
+                b'/' => {
+                    self_closing = true;
+                    index += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let attr_end = index + bytes[index..]
+                .iter()
+                .take_while(|b| !b.is_ascii_whitespace() && !matches!(b, b'=' | b'>' | b'/'))
+                .count();
+            let attr = value[index..attr_end].to_ascii_lowercase();
+            index = attr_end;
+            while bytes.get(index)?.is_ascii_whitespace() {
+                index += 1;
+            }
+            if bytes[index] != b'=' {
+                attributes.push((attr, None));
+                continue;
+            }
+            index += 1;
+            while bytes.get(index)?.is_ascii_whitespace() {
+                index += 1;
+            }
+            let attr_value = match bytes[index] {
+                quote @ (b'"' | b'\'') => {
+                    let end = index + 1 + value[index + 1..].find(quote as char)?;
+                    let attr_value = &value[index + 1..end];
+                    index = end + 1;
+                    attr_value
+                }
+                _ => {
+                    let end = index + bytes[index..]
+                        .iter()
+                        .take_while(|b| !b.is_ascii_whitespace() && **b != b'>')
+                        .count();
+                    let attr_value = &value[index..end];
+                    index = end;
+                    attr_value
+                }
+            };
+            attributes.push((attr, Some(decode_entities(attr_value))));
+        }
+        Some(Tag {
+            name,
+            closing,
+            attributes,
+            self_closing,
+            len: index + 1,
+        })
+    }
+
+    /// The tag with allowed attributes only, their values encoded.
+    fn html(&self) -> String {
+        if self.closing {
+            return format!("</{}>", self.name);
+        }
+        let mut html = format!("<{}", self.name);
+        for (name, value) in &self.attributes {
+            if !SAFE_ATTRIBUTES.contains(&name.as_str()) {
+                continue;
+            }
+            let Some(value) = value else {
+                html += &format!(" {name}");
+                continue;
+            };
+            let value = match name.as_str() {
+                "href" | "cite" if !url_allowed(value, SAFE_HREF_PROTOCOLS) => String::new(),
+                "src" if !url_allowed(value, SAFE_SRC_PROTOCOLS) => String::new(),
+                // candidates are separated by commas, each can have its own protocol
+                "srcset" if !value
+                    .split(',')
+                    .all(|candidate| url_allowed(candidate.trim(), SAFE_SRC_PROTOCOLS)) =>
+                {
+                    String::new()
+                }
+                "href" | "cite" | "src" => url_encode(value),
+                _ => text_encode(value),
+            };
+            html += &format!(" {name}=\"{value}\"");
+        }
+        if self.self_closing {
+            html += " /";
+        }
+        html + ">"
+    }
+}
+
+/// Decodes the character references that can hide a protocol, e.g. `javascript&#58;`. Others
+/// are left as they are, so they're shown as written once the value is encoded again.
+fn decode_entities(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        result += &rest[..start];
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "colon" => ':',
+                "tab" => '\t',
+                "newline" => '\n',
+                reference => {
+                    let number = reference.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+            };
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                result.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result + rest
+}
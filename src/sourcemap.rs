@@ -0,0 +1,144 @@
+//! Version 3 source maps from generated svelte back to the markdown source.
+//!
+//! While rendering, the output of every node is prefixed with a marker holding the index of its
+//! source offset. Once the whole component is assembled, [`extract`] removes the markers and
+//! turns their generated positions into mappings, so escaping and wrapping done after rendering
+//! can't shift them.
+
+use serde::Serialize;
+
+const MARK_START: char = '\u{E000}';
+const MARK_END: char = '\u{E001}';
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceMap {
+    pub version: u8,
+    pub sources: Vec<String>,
+    pub sources_content: Vec<String>,
+    pub names: Vec<String>,
+    pub mappings: String,
+}
+
+impl SourceMap {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Marker for the `index`th recorded source offset.
+pub fn mark(index: usize) -> String {
+    format!("{MARK_START}{index}{MARK_END}")
+}
+
+/// Removes markers from `output`, mapping each to the source offset it was recorded with.
+pub fn extract(
+    output: &str,
+    source: &str,
+    offsets: &[usize],
+    file: Option<&str>,
+) -> (String, SourceMap) {
+    let lines = Lines::new(source);
+    let mut code = String::with_capacity(output.len());
+    let mut mappings = String::new();
+    // generated column resets every line, source fields are relative to the previous segment
+    let (mut column, mut previous_column) = (0, 0);
+    let (mut previous_line, mut previous_source_column) = (0, 0);
+    let mut first_in_line = true;
+
+    let mut chars = output.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == MARK_START {
+            let rest = &output[i + c.len_utf8()..];
+            let marker = rest
+                .find(MARK_END)
+                .and_then(|end| Some((end, rest[..end].parse::<usize>().ok()?)))
+                .and_then(|(end, index)| Some((end, *offsets.get(index)?)));
+            if let Some((end, offset)) = marker {
+                let (line, source_column) = lines.position(offset);
+                if !first_in_line {
+                    mappings.push(',');
+                }
+                vlq(&mut mappings, column - previous_column);
+                vlq(&mut mappings, 0);
+                vlq(&mut mappings, line - previous_line);
+                vlq(&mut mappings, source_column - previous_source_column);
+                previous_column = column;
+                previous_line = line;
+                previous_source_column = source_column;
+                first_in_line = false;
+                // skip the index and the end marker
+                for _ in rest[..end + MARK_END.len_utf8()].chars() {
+                    chars.next();
+                }
+                continue;
+            }
+        }
+        code.push(c);
+        if c == '\n' {
+            mappings.push(';');
+            column = 0;
+            previous_column = 0;
+            first_in_line = true;
+        } else {
+            column += c.len_utf16() as i64;
+        }
+    }
+
+    let map = SourceMap {
+        version: 3,
+        sources: vec![file.unwrap_or_default().to_string()],
+        sources_content: vec![source.to_string()],
+        names: Vec::new(),
+        mappings,
+    };
+    (code, map)
+}
+
+/// Zero-based line and UTF-16 column of byte offsets in the source.
+struct Lines<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(source: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Lines { source, starts }
+    }
+
+    fn position(&self, offset: usize) -> (i64, i64) {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let start = self.starts[line];
+        let column = self
+            .source
+            .get(start..offset)
+            .map_or(0, |text| text.encode_utf16().count());
+        (line as i64, column as i64)
+    }
+}
+
+/// Appends a base64 VLQ encoded number.
+fn vlq(out: &mut String, value: i64) {
+    let mut value = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = value & 0b11111;
+        value >>= 5;
+        if value > 0 {
+            digit |= 0b100000;
+        }
+        out.push(BASE64[digit as usize] as char);
+        if value == 0 {
+            break;
+        }
+    }
+}
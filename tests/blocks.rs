@@ -0,0 +1,79 @@
+mod common;
+
+use common::options;
+use mdsvexrs::Context;
+
+fn convert(input: &str) -> String {
+    Context::new(options()).convert(input).unwrap()
+}
+
+#[test]
+fn table_has_head_and_body() {
+    let output = convert("| a | b |\n|---|---|\n| 1 | 2 |\n");
+    assert!(output.contains(
+        "<table ><thead ><tr ><th >a</th><th >b</th></tr></thead>\
+         <tbody ><tr ><td >1</td><td >2</td></tr></tbody></table>"
+    ));
+}
+
+#[test]
+fn table_without_rows_has_no_body() {
+    let output = convert("| a |\n|---|\n");
+    assert!(output.contains("<table ><thead ><tr ><th >a</th></tr></thead></table>"));
+}
+
+#[test]
+fn rows_are_padded_and_truncated_to_the_columns() {
+    let output = convert("| a | b | c |\n|---|---|---|\n| 1 |\n| 1 | 2 | 3 | 4 |\n");
+    assert!(output.contains("<tr ><td >1</td><td ></td><td ></td></tr>"));
+    assert!(output.contains("<tr ><td >1</td><td >2</td><td >3</td></tr>"));
+    assert!(!output.contains("<td >4</td>"));
+}
+
+#[test]
+fn cells_are_aligned() {
+    let output = convert("| a | b | c | d |\n|:--|:-:|--:|---|\n| 1 | 2 | 3 | 4 |\n");
+    assert!(output.contains(
+        r#"<th align="left">a</th><th align="center">b</th><th align="right">c</th><th >d</th>"#
+    ));
+    assert!(output.contains(
+        r#"<td align="left">1</td><td align="center">2</td><td align="right">3</td><td >4</td>"#
+    ));
+}
+
+#[test]
+fn ordered_list_start() {
+    assert!(convert("3. a\n4. b\n").contains(r#"<ol start="3"><li >a</li><li >b</li></ol>"#));
+    assert!(convert("1. a\n").contains("<ol ><li >a</li></ol>"));
+}
+
+#[test]
+fn tight_lists_have_no_paragraphs() {
+    assert!(convert("- a\n- b\n").contains("<ul ><li >a</li><li >b</li></ul>"));
+}
+
+#[test]
+fn loose_lists_have_paragraphs() {
+    let output = convert("- a\n\n- b\n");
+    assert!(output.contains("<ul ><li ><p >a</p></li><li ><p >b</p></li></ul>"));
+}
+
+#[test]
+fn tight_task_items_start_with_the_checkbox() {
+    let output = convert("- [x] a\n- [ ] b\n");
+    assert!(output.contains(
+        r#"<ul class="contains-task-list"><li class="task-list-item"><input type="checkbox" disabled checked /> a</li>"#
+    ));
+    assert!(output.contains(r#"<li class="task-list-item"><input type="checkbox" disabled /> b</li>"#));
+}
+
+#[test]
+fn loose_task_items_have_the_checkbox_in_the_first_paragraph() {
+    let output = convert("- [x] a\n\n  more\n- [ ] b\n");
+    assert!(output.contains(
+        r#"<li class="task-list-item"><p ><input type="checkbox" disabled checked /> a</p><p >more</p></li>"#
+    ));
+    assert!(output.contains(
+        r#"<li class="task-list-item"><p ><input type="checkbox" disabled /> b</p></li>"#
+    ));
+}
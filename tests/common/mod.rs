@@ -0,0 +1,21 @@
+use mdsvexrs::{HeadingLinks, MdsvexrsOptions};
+
+/// Options like the CLI's defaults.
+pub fn options() -> MdsvexrsOptions {
+    MdsvexrsOptions {
+        layout: String::new(),
+        layouts: Default::default(),
+        layout_globs: Vec::new(),
+        custom_tags: Vec::new(),
+        math_fallback: Default::default(),
+        target: Default::default(),
+        heading_links: HeadingLinks::None,
+        toc_min_depth: 1,
+        toc_max_depth: 6,
+        heading_link_content: "#".to_string(),
+        heading_link_class: String::new(),
+        source_map: false,
+        path: None,
+        safe: false,
+    }
+}
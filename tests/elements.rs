@@ -0,0 +1,129 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, CustomTag, MdsvexrsError};
+
+fn convert(input: &str) -> String {
+    let mut options = options();
+    options.layout = "L".to_string();
+    Context::new(options).convert(input).unwrap()
+}
+
+#[test]
+fn second_instance_script_is_an_error() {
+    let err = Context::new(options())
+        .render("<script>let a;</script>\n\ntext\n\n<script>let b;</script>\n")
+        .err()
+        .unwrap();
+    let MdsvexrsError::DuplicateElement { pos, .. } = err else {
+        panic!("expected a duplicate element, got {err}");
+    };
+    assert_eq!(pos.unwrap().start.line, 5);
+}
+
+#[test]
+fn module_script_gets_the_metadata_export() {
+    let output = convert("---\na: 1\n---\n\n<script context=\"module\">export const x = 1;</script>\n");
+    let (module, _) = output.split_once('\n').unwrap();
+    assert!(module.starts_with(r#"<script context="module">export const metadata = {"a":1,"#));
+    assert!(module.ends_with("};export const x = 1;</script>"), "{module}");
+    assert_eq!(output.matches("<script context=\"module\">").count(), 1);
+}
+
+#[test]
+fn style_is_moved_to_the_end() {
+    let output = convert("<style>p { color: red }</style>\n\n# A\n\ntext\n");
+    assert!(output.ends_with("</MDXLayout>\n<style>p { color: red }</style>"), "{output}");
+    assert_eq!(output.matches("color: red").count(), 1);
+}
+
+#[test]
+fn elements_in_code_are_left_alone() {
+    let input = "<script>let a;</script>\n\n```\n<script>let b;</script>\n<style>q {}</style>\n```\n\n\
+                 `<script>let c;</script>`\n";
+    let output = convert(input);
+    assert!(output.contains("<script>import MDXLayout from \"L\";let a;</script>"));
+    assert!(output.contains("&lt;script&gt;let b;&lt;/script&gt;"));
+    assert!(output.contains("&lt;script&gt;let c;&lt;/script&gt;"));
+    assert!(!output.contains("<style>"));
+
+    // indented code, at the top level and in list items
+    let output = convert("    <script>alert(1)</script>\n\n- item\n\n      <style>x {}</style>\n");
+    assert!(!output.contains("alert(1)</script>"));
+    assert!(output.contains("<pre ><code >&lt;script&gt;alert(1)&lt;/script&gt;</code></pre>"));
+    assert!(output.contains("&lt;style&gt;x &#123;&#125;&lt;/style&gt;"));
+    assert!(!output.contains("<style>"));
+}
+
+#[test]
+fn indented_lines_continuing_a_paragraph_are_not_code() {
+    let output = convert("text\n    <style>p {}</style>\n\n- item\n  <script>let a;</script>\n");
+    assert!(output.ends_with("<style>p {}</style>"), "{output}");
+    assert!(output.contains("<script>import MDXLayout from \"L\";let a;</script>"));
+}
+
+#[test]
+fn typescript_opening_tag_is_kept() {
+    let output = convert("<script lang=\"ts\" generics=\"T extends A<B>\">let a: T;</script>\n");
+    assert!(output.contains(
+        r#"<script lang="ts" generics="T extends A<B>">import MDXLayout from "L";let a: T;</script>"#
+    ));
+    // the generated module script is typescript too
+    assert!(output.starts_with(r#"<script context="module" lang="ts">"#));
+}
+
+#[test]
+fn generated_imports_are_renamed_when_they_clash() {
+    let mut options = options();
+    options.layout = "L".to_string();
+    options.custom_tags = vec!["a=Link:$lib/Link.svelte".parse().unwrap()];
+    let input = "<script>import MDXLayout from \"./x\";\nimport Link from \"./y\";</script>\n\n[a](/a)\n";
+    let output = Context::new(options).convert(input).unwrap();
+    assert!(output.contains(r#"import MDXLayout_1 from "L";import Link_1 from "$lib/Link.svelte";"#));
+    assert!(output.contains("<MDXLayout_1 {...metadata} {...$$restProps}>"));
+    assert!(output.contains(r#"<Link_1 href="/a">a</Link_1>"#));
+    assert!(output.contains("import MDXLayout from \"./x\";"));
+}
+
+#[test]
+fn clashing_component_names_are_errors() {
+    for tags in [
+        ["a=Link", "img=Link:$lib/Image.svelte"],
+        ["h1=H:$lib/H1.svelte", "h2=H:$lib/H2.svelte"],
+        ["a=MDXLayout:$lib/Link.svelte", "img"],
+    ] {
+        let mut options = options();
+        options.layout = "L".to_string();
+        options.custom_tags = tags.iter().map(|tag| tag.parse().unwrap()).collect();
+        let err = Context::new(options).render("# A").err().unwrap();
+        assert!(matches!(err, MdsvexrsError::ComponentClash { .. }), "{err}");
+    }
+}
+
+#[test]
+fn elements_can_share_a_component() {
+    let mut options = options();
+    options.custom_tags = vec![
+        "h1=H:$lib/H.svelte".parse().unwrap(),
+        "h2=H:$lib/H.svelte".parse().unwrap(),
+    ];
+    let output = Context::new(options).convert("# A\n\n## B\n").unwrap();
+    assert_eq!(output.matches("import H from").count(), 1);
+}
+
+#[test]
+fn component_names_have_to_be_identifiers() {
+    assert!("a=my-link:$lib/Link.svelte".parse::<CustomTag>().is_err());
+}
+
+#[test]
+fn void_components_are_self_closed() {
+    let mut options = options();
+    options.custom_tags = ["img=Image:$lib/Image.svelte", "hr=Rule:$lib/Rule.svelte"]
+        .into_iter()
+        .map(|tag| tag.parse().unwrap())
+        .collect();
+    let output = Context::new(options).convert("![i](/y)\n\n---\n\ntext\n").unwrap();
+    assert!(output.contains(r#"<Image src="/y" alt="i" />"#), "{output}");
+    assert!(output.contains("<Rule />"), "{output}");
+}
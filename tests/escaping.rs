@@ -0,0 +1,88 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, CustomTag, HeadingLinks};
+
+fn convert(input: &str) -> String {
+    Context::new(options()).convert(input).unwrap()
+}
+
+/// Converts with a layout and `a`, `img` and `pre` rendered as components, so everything ends
+/// up in svelte markup instead of `{@html}`.
+fn convert_in_components(input: &str) -> String {
+    let mut options = options();
+    options.layout = "$lib/layout.svelte".to_string();
+    options.custom_tags = ["a", "img", "pre"]
+        .into_iter()
+        .map(|tag| tag.parse::<CustomTag>().unwrap())
+        .collect();
+    Context::new(options).convert(input).unwrap()
+}
+
+#[test]
+fn link_title_quotes_are_encoded() {
+    let output = convert(r#"[a](/a "say \"hi\" <b>")"#);
+    assert!(output.contains(r#"<a href="/a" title="say &quot;hi&quot; &lt;b&gt;">a</a>"#));
+}
+
+#[test]
+fn link_url_is_percent_encoded() {
+    let output = convert("[a](</a b/é?q=\"1\"&x=2>)");
+    assert!(output.contains(r#"href="/a%20b/%C3%A9?q=%221%22&amp;x=2""#));
+}
+
+#[test]
+fn valid_percent_escapes_are_kept() {
+    let output = convert("[a](/a%20b%2F)");
+    assert!(output.contains(r#"href="/a%20b%2F""#));
+}
+
+#[test]
+fn literal_braces_in_url_are_percent_encoded() {
+    let output = convert_in_components(r"[a](/\{id\})");
+    assert!(output.contains(r#"<A href="/%7Bid%7D">a</A>"#));
+}
+
+#[test]
+fn literal_braces_in_title_are_escaped() {
+    let output = convert_in_components(r#"[a](/a "\{x\}")"#);
+    assert!(output.contains(r#"title="&#123;x&#125;""#));
+}
+
+#[test]
+fn image_alt_is_encoded() {
+    let output = convert_in_components(r#"![a "b" & \{c\}](/i.png 't')"#);
+    let alt = r#"alt="a &quot;b&quot; &amp; &#123;c&#125;""#;
+    assert!(output.contains(&format!(r#"<IMG src="/i.png" {alt} title="t" />"#)));
+}
+
+#[test]
+fn svelte_tags_in_attributes_stay_expressions() {
+    let output = convert(r#"[a]({base}/page "{title}") ![{alt}]({src})"#);
+    assert!(output.contains(r#"<a href="{base}/page" title="{title}">a</a>"#));
+    assert!(output.contains(r#"<img src="{src}" alt="{alt}" />"#));
+}
+
+#[test]
+fn reference_definitions_are_encoded() {
+    let output = convert("[a] ![b][a]\n\n[a]: /x\\{y\\}é \"t\\\"{t}\"\n");
+    assert!(output.contains(r#"<a href="/x%7By%7D%C3%A9" title="t&quot;{t}">a</a>"#));
+    assert!(output.contains(r#"<img src="/x%7By%7D%C3%A9" alt="b" title="t&quot;{t}" />"#));
+}
+
+#[test]
+fn code_braces_are_escaped_in_components() {
+    let output = convert_in_components("```js\nlet a = {b};\n```\n\n```\n{c}\n```\n");
+    assert!(!output.contains("{b}"));
+    assert!(!output.contains("{c}"));
+    assert!(output.contains("&#123;c&#125;"));
+}
+
+#[test]
+fn template_literal_interpolation_is_escaped() {
+    let mut options = options();
+    options.heading_links = HeadingLinks::Prepend;
+    options.heading_link_content = "${x}`".to_string();
+    let output = Context::new(options).convert("# a").unwrap();
+    assert!(output.contains(r"\${x}\`"));
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, RenderOutput};
+
+fn render(input: &str) -> RenderOutput {
+    Context::new(options()).render(input).unwrap()
+}
+
+#[test]
+fn footnotes_are_numbered_by_first_reference() {
+    let output = render("a[^b] c[^a]\n\n[^a]: first\n[^b]: second\n[^c]: unused\n");
+    assert!(output.code.contains(r##"<a href="#fn:1" id="fnref:1" data-footnote-ref"##));
+    assert!(output.code.contains(r#"<li id="fn:1"><p >second "#));
+    assert!(output.code.contains(r#"<li id="fn:2"><p >first "#));
+    assert!(!output.code.contains("unused"));
+}
+
+#[test]
+fn repeated_references_get_their_own_ids_and_back_links() {
+    let output = render("a[^x] b[^x]\n\n[^x]: note\n");
+    assert!(output.code.contains(r#"id="fnref:1""#));
+    assert!(output.code.contains(r#"id="fnref:1:2""#));
+    assert!(output.code.contains(
+        r##"<a href="#fnref:1" data-footnote-backref aria-label="Back to reference 1" class="data-footnote-backref">↩</a>"##
+    ));
+    assert!(output.code.contains(r##"<a href="#fnref:1:2" data-footnote-backref"##));
+    assert!(output.code.contains("↩<sup>2</sup></a>"));
+}
+
+#[test]
+fn footnotes_can_reference_footnotes() {
+    let output = render("a[^x]\n\n[^x]: see[^y]\n[^y]: more\n");
+    assert!(output.code.contains(r##"<li id="fn:1"><p >see<sup ><a href="#fn:2" id="fnref:2""##));
+    assert!(output.code.contains(r#"<li id="fn:2"><p >more "#));
+}
+
+#[test]
+fn footnotes_are_in_metadata_without_frontmatter() {
+    let output = render("a[^x] b[^x]\n\n[^x]: note\n");
+    let footnotes = output.metadata["footnotes"].as_array().unwrap();
+    assert_eq!(footnotes.len(), 1);
+    assert_eq!(footnotes[0]["id"], "fn:1");
+    assert_eq!(footnotes[0]["label"], "x");
+    assert_eq!(footnotes[0]["references"], 2);
+    assert!(output.code.contains(r#"export const metadata = {"footnotes":[{"id":"fn:1""#));
+}
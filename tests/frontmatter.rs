@@ -0,0 +1,55 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, MdsvexrsError, RenderOutput};
+
+fn render(input: &str) -> Result<RenderOutput, MdsvexrsError> {
+    Context::new(options()).render(input)
+}
+
+/// Line, column and offset of a frontmatter error.
+fn error_at(input: &str) -> (usize, usize, usize) {
+    let err = render(input).err().unwrap();
+    assert!(matches!(err, MdsvexrsError::Frontmatter { .. }), "{err}");
+    let start = &err.pos().unwrap().start;
+    (start.line, start.column, start.offset)
+}
+
+#[test]
+fn toml_frontmatter() {
+    let output = render("+++\ntitle = \"T\"\nn = [1, 2]\n+++\n\n# A\n").unwrap();
+    assert_eq!(output.metadata["title"], "T");
+    assert_eq!(output.metadata["n"], serde_json::json!([1, 2]));
+    assert_eq!(output.titles[0].pos.as_ref().unwrap().start.line, 6);
+}
+
+#[test]
+fn toml_errors_point_into_the_document() {
+    assert_eq!(error_at("+++\ntitle = \n+++\n").0, 2);
+}
+
+#[test]
+fn json_frontmatter() {
+    let output = render("{\n  \"title\": \"T\",\n  \"n\": [1, 2]\n}\n\n# A\n").unwrap();
+    assert_eq!(output.metadata["title"], "T");
+    assert_eq!(output.metadata["n"], serde_json::json!([1, 2]));
+    assert_eq!(output.titles[0].pos.as_ref().unwrap().start.line, 6);
+    assert!(!output.code.contains("title&quot;"));
+}
+
+#[test]
+fn json_errors_have_offsets() {
+    // columns count characters, not bytes
+    assert_eq!(error_at("{\n  \"a\": 1,\n  \"é\": x\n}\n"), (3, 8, 20));
+}
+
+#[test]
+fn json_error_at_end_of_input() {
+    // serde_json reports column 0 there
+    assert_eq!(error_at("{\n"), (2, 1, 2));
+}
+
+#[test]
+fn json_frontmatter_has_to_end_its_line() {
+    assert_eq!(error_at("{\n  \"a\": 1\n} x\n"), (3, 2, 12));
+}
@@ -0,0 +1,40 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, CustomTag, HeadingLinks};
+
+#[test]
+fn custom_ids_cannot_clash_with_footnotes() {
+    let output = Context::new(options())
+        .render("## X {#fn:1}\n\na[^n]\n\n[^n]: note\n")
+        .unwrap();
+    assert_eq!(output.titles[0].id, "x");
+    assert_eq!(output.code.matches(r#"id="fn:1""#).count(), 1);
+    assert_eq!(output.warnings.len(), 1);
+}
+
+#[test]
+fn heading_links_can_be_imported_from_the_layout() {
+    let mut options = options();
+    options.layout = "L".to_string();
+    options.heading_links = HeadingLinks::Append;
+    options.custom_tags = vec!["headingLink=Anchor".parse().unwrap()];
+    let output = Context::new(options).convert("# A").unwrap();
+    assert!(output.contains(r#"import MDXLayout, { headingLink as Anchor } from "L";"#));
+    assert!(output.contains(r##"<Anchor href="#a""##));
+}
+
+#[test]
+fn layout_imports_need_identifiers() {
+    assert!("heading-link=Anchor".parse::<CustomTag>().is_err());
+    assert!("heading-link=Anchor:$lib/Anchor.svelte".parse::<CustomTag>().is_ok());
+}
+
+#[test]
+fn toc_is_in_metadata_without_frontmatter() {
+    let output = Context::new(options()).render("# A\n\n## B\n").unwrap();
+    let toc = output.metadata["toc"].as_array().unwrap();
+    assert_eq!(toc.len(), 1);
+    assert_eq!(toc[0]["children"][0]["id"], "b");
+    assert!(output.code.contains(r#""toc":[{"children":[{"children":[],"id":"b""#));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, MdsvexrsError, MdsvexrsOptions};
+
+fn layout_options() -> MdsvexrsOptions {
+    let mut options = options();
+    options.layout = "./Default.svelte".to_string();
+    options.layouts = [("blog", "./Blog.svelte"), ("docs", "./Docs.svelte")]
+        .into_iter()
+        .map(|(name, layout)| (name.to_string(), layout.to_string()))
+        .collect();
+    options
+}
+
+fn imported_layout(options: MdsvexrsOptions, input: &str) -> Option<String> {
+    let code = Context::new(options).convert(input).unwrap();
+    let (_, rest) = code.split_once("import MDXLayout from \"")?;
+    Some(rest[..rest.find('"').unwrap()].to_string())
+}
+
+#[test]
+fn default_layout() {
+    let layout = imported_layout(layout_options(), "# A\n");
+    assert_eq!(layout.as_deref(), Some("./Default.svelte"));
+}
+
+#[test]
+fn named_layout_from_frontmatter() {
+    let layout = imported_layout(layout_options(), "---\nlayout: blog\n---\n# A\n");
+    assert_eq!(layout.as_deref(), Some("./Blog.svelte"));
+}
+
+#[test]
+fn layout_false_renders_without_a_layout() {
+    let code = Context::new(layout_options())
+        .convert("---\nlayout: false\n---\n# A\n")
+        .unwrap();
+    assert!(!code.contains("MDXLayout"));
+}
+
+#[test]
+fn unknown_layout_is_an_error() {
+    let err = Context::new(layout_options())
+        .render("---\nlayout: nope\n---\n# A\n")
+        .err()
+        .unwrap();
+    assert!(matches!(&err, MdsvexrsError::UnknownLayout { name, pos: Some(_) } if name == "nope"));
+    let err = Context::new(layout_options()).render("---\nlayout: 1\n---\n").err().unwrap();
+    assert!(matches!(err, MdsvexrsError::InvalidLayout { .. }));
+}
+
+#[test]
+fn first_matching_glob_wins() {
+    let mut options = layout_options();
+    options.layout_globs = vec![
+        ("blog/drafts/**".to_string(), "docs".to_string()),
+        ("blog/**".to_string(), "blog".to_string()),
+    ];
+    let layout_for = |path: &str| {
+        let mut options = options.clone();
+        options.path = Some(path.to_string());
+        imported_layout(options, "# A\n")
+    };
+    assert_eq!(layout_for("blog/drafts/a.md").as_deref(), Some("./Docs.svelte"));
+    assert_eq!(layout_for("blog/a.md").as_deref(), Some("./Blog.svelte"));
+    assert_eq!(layout_for("a.md").as_deref(), Some("./Default.svelte"));
+}
+
+#[test]
+fn frontmatter_overrides_globs() {
+    let mut options = layout_options();
+    options.layout_globs = vec![("**".to_string(), "blog".to_string())];
+    options.path = Some("a.md".to_string());
+    let layout = imported_layout(options, "---\nlayout: docs\n---\n# A\n");
+    assert_eq!(layout.as_deref(), Some("./Docs.svelte"));
+}
+
+#[test]
+fn glob_naming_an_unknown_layout_is_an_error() {
+    let mut options = layout_options();
+    options.layout_globs = vec![("**".to_string(), "nope".to_string())];
+    options.path = Some("a.md".to_string());
+    let err = Context::new(options).render("# A\n").err().unwrap();
+    assert!(matches!(&err, MdsvexrsError::UnknownLayout { name, pos: None } if name == "nope"));
+}
+
+#[test]
+fn invalid_glob_is_an_error() {
+    let mut options = layout_options();
+    options.layout_globs = vec![("a[".to_string(), "blog".to_string())];
+    options.path = Some("a.md".to_string());
+    let err = Context::new(options).render("# A\n").err().unwrap();
+    assert!(matches!(err, MdsvexrsError::LayoutGlob { .. }));
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{
+    math::{escape, to_mathml, MathError},
+    Context,
+};
+
+/// The `<mrow>` of the MathML, without the TeX annotation.
+fn mathml(tex: &str) -> String {
+    let mathml = to_mathml(tex).unwrap();
+    let start = "<semantics><mrow>".len();
+    let end = mathml.find("<annotation").unwrap() - "</mrow>".len();
+    mathml[start..end].to_string()
+}
+
+#[test]
+fn fractions() {
+    assert_eq!(
+        mathml(r"\frac{a}{b}"),
+        "<mfrac><mrow><mi>a</mi></mrow><mrow><mi>b</mi></mrow></mfrac>"
+    );
+}
+
+#[test]
+fn scripts() {
+    assert_eq!(mathml("x^2_i"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+}
+
+#[test]
+fn double_scripts_are_errors() {
+    assert_eq!(to_mathml("x^2^3"), Err(MathError::DoubleScript));
+    assert_eq!(to_mathml("x_1_2"), Err(MathError::DoubleScript));
+}
+
+#[test]
+fn left_right() {
+    assert_eq!(
+        mathml(r"\left( x \right)"),
+        "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi>\
+         <mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+    );
+    assert_eq!(to_mathml(r"\left( x"), Err(MathError::UnexpectedEnd));
+}
+
+#[test]
+fn matrices() {
+    assert_eq!(
+        mathml(r"\begin{pmatrix} a & b \\ c & d \end{pmatrix}"),
+        "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable columnalign=\"center\">\
+         <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+         <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>\
+         </mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+    );
+    assert!(mathml(r"\begin{bmatrix} a \end{bmatrix}").contains("stretchy=\"true\">[</mo>"));
+    assert!(mathml(r"\begin{cases} a & b \end{cases}").contains("columnalign=\"left left\""));
+}
+
+#[test]
+fn text() {
+    assert_eq!(mathml(r"\text{if } x"), "<mtext>if </mtext><mi>x</mi>");
+}
+
+#[test]
+fn unsupported_input_is_an_error() {
+    assert_eq!(to_mathml(r"\foo"), Err(MathError::UnsupportedCommand("foo".to_string())));
+    assert_eq!(
+        to_mathml(r"\begin{foo} x \end{foo}"),
+        Err(MathError::UnsupportedEnvironment("foo".to_string()))
+    );
+    assert_eq!(to_mathml("a}"), Err(MathError::Unexpected("}".to_string())));
+}
+
+#[test]
+fn output_is_safe_in_svelte_markup() {
+    for tex in [r"\frac{a}{b}", r"\{ x \}", r"\text{${x}` $}", r"\begin{cases} a \end{cases}"] {
+        let mathml = to_mathml(tex).unwrap();
+        assert!(!mathml.contains(['{', '}', '`', '$']), "{tex} gave {mathml}");
+    }
+    assert_eq!(escape("{`$x`}"), "&#123;&#96;&#36;x&#96;&#125;");
+}
+
+#[test]
+fn unsupported_math_falls_back_with_a_warning() {
+    let output = Context::new(options()).render(r"a $\foo{x}$ b").unwrap();
+    assert!(output
+        .code
+        .contains(r#"<code class="math math-inline">&#92;foo&#123;x&#125;</code>"#));
+    assert_eq!(output.warnings.len(), 1);
+    assert!(output.warnings[0].message.contains(r"unsupported command \foo"));
+}
+
+#[test]
+fn math_component_gets_tex_and_display() {
+    let mut options = options();
+    options.custom_tags = vec!["math=Math:$lib/Math.svelte".parse().unwrap()];
+    let output = Context::new(options).render("$x$ $\\foo$\n\n$$\n\\frac{1}{2}\n$$\n").unwrap();
+    assert!(output.code.contains(
+        r#"<Math xmlns="http://www.w3.org/1998/Math/MathML" tex="x" display={false}><semantics>"#
+    ));
+    // unsupported math goes through the component too
+    assert!(output.code.contains(
+        r#"<Math tex="&#92;foo" display={false}><code class="math math-inline">&#92;foo</code></Math>"#
+    ));
+    assert!(output.code.contains(r#"tex="&#92;frac&#123;1&#125;&#123;2&#125;" display={true}>"#));
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::options;
+use mdsvexrs::Context;
+
+fn convert(input: &str) -> String {
+    Context::new(options()).convert(input).unwrap()
+}
+
+#[test]
+fn markdown_inside_tags_is_not_parsed() {
+    let output = convert("{a * b * c} and {x_y_z}");
+    assert!(output.contains("<p >{a * b * c}{@html ` and `}{x_y_z}</p>"));
+}
+
+#[test]
+fn blocks_span_paragraphs() {
+    let output = convert("{#if a}\n\nx **y**\n\n{:else}\n\nz\n\n{/if}\n");
+    assert!(output.contains(
+        "{#if a}\n{@html `<p >x <strong >y</strong></p>`}\n{:else}\n{@html `<p >z</p>`}\n{/if}"
+    ));
+}
+
+#[test]
+fn escaped_and_empty_braces_are_literal() {
+    let output = convert(r"\{a\} and {} here");
+    assert!(output.contains("<p >&#123;a&#125; and &#123;&#125; here</p>"));
+}
+
+#[test]
+fn braces_in_code_and_math_are_left_alone() {
+    let output = convert("`{a}` and $x^{2}$\n\n```\n{b}\n```\n");
+    assert!(output.contains("<code >&#123;a&#125;</code>"));
+    assert!(output.contains("<msup><mi>x</mi><mrow><mn>2</mn></mrow></msup>"));
+    assert!(output.contains("&#123;b&#125;"));
+    assert!(!output.contains("{a}") && !output.contains("{b}"));
+}
+
+#[test]
+fn template_literals_inside_tags_are_kept() {
+    let output = convert("{`a ${b} c`} and [l]({`/a/${b}`})");
+    assert!(output.contains("{`a ${b} c`}"));
+    assert!(output.contains(r#"<a href="{`/a/${b}`}">l</a>"#));
+    assert!(!output.contains(r"\${b}"));
+}
+
+#[test]
+fn text_shaped_like_a_placeholder_stays_text() {
+    let output = convert(r"\{xxx\} and {abc}");
+    assert!(output.contains("<p >{@html `&#123;xxx&#125; and `}{abc}</p>"), "{output}");
+    let output = convert("&#123;xxx&#125; {abc} &#128;xx} {d}");
+    assert!(output.contains("{@html `&#123;xxx&#125; `}{abc}"), "{output}");
+    assert!(output.contains("xx&#125; `}{d}"), "{output}");
+}
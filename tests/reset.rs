@@ -0,0 +1,28 @@
+mod common;
+
+use common::options;
+use mdsvexrs::Context;
+
+#[test]
+fn nothing_leaks_into_the_next_document() {
+    let mut ctx = Context::new(options());
+    let first = "---\ntitle: First\n---\n\n<script>let a = 1;</script>\n\
+                 <script context=\"module\">let b = 2;</script>\n<style>p { color: red }</style>\n\n\
+                 # One\n\ntext[^n] $\\foo$\n\n[^n]: note\n";
+    let output = ctx.render(first).unwrap();
+    assert_eq!(output.metadata["footnotes"].as_array().unwrap().len(), 1);
+    assert!(output.script.is_some() && output.module_script.is_some() && output.style.is_some());
+
+    let output = ctx.render("---\nx: 1\n---\n\n# One\n").unwrap();
+    assert_eq!(output.titles.len(), 1);
+    // the slug counter starts over too
+    assert_eq!(output.titles[0].id, "one");
+    assert!(!output.metadata.contains_key("title"));
+    assert_eq!(output.metadata["titles"].as_array().unwrap().len(), 1);
+    assert_eq!(output.metadata["footnotes"].as_array().unwrap().len(), 0);
+    assert!(output.script.is_none() && output.module_script.is_none() && output.style.is_none());
+    assert!(output.warnings.is_empty());
+    for leaked in ["let a", "let b", "color", "fn:1", "First"] {
+        assert!(!output.code.contains(leaked), "{leaked} leaked into {}", output.code);
+    }
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use common::options;
+use mdsvexrs::Context;
+
+fn convert(input: &str) -> String {
+    let mut options = options();
+    options.safe = true;
+    Context::new(options).convert(input).unwrap()
+}
+
+#[test]
+fn svelte_tags_are_text() {
+    let output = convert("{name} {#if a}b{/if} [l]({url})");
+    assert!(output.contains("&#123;name&#125; &#123;#if a&#125;b&#123;/if&#125;"));
+    assert!(output.contains(r#"href="%7Burl%7D""#));
+}
+
+#[test]
+fn scripts_and_styles_are_dropped() {
+    let output = convert("<script>let a = 1;</script>\n\n<style>p { color: red }</style>\n\ntext");
+    assert!(!output.contains("let a"));
+    assert!(!output.contains("color"));
+    assert!(output.contains("<p >text</p>"));
+}
+
+#[test]
+fn html_is_reduced_to_allowed_elements_and_attributes() {
+    let output = convert(
+        "<div class=\"a\" onclick=\"x()\" style=\"color: red\"><iframe src=\"/\">i</iframe>\
+         <blink>b</blink> {c}</div>",
+    );
+    assert!(output.contains("<div class=\"a\">b &#123;c&#125;</div>"));
+}
+
+#[test]
+fn dangerous_protocols_are_emptied() {
+    let output = convert(
+        "[a](javascript:alert(1)) ![b](data:image/png,x) [c](mailto:a@b.c) [d](/e:f)\n\n\
+         <a href=\"JavaScript&#58;alert(1)\">e</a>",
+    );
+    assert!(output.contains(r#"<a href="">a</a>"#));
+    assert!(output.contains(r#"<img src="" alt="b" />"#));
+    assert!(output.contains(r#"<a href="mailto:a@b.c">c</a>"#));
+    assert!(output.contains(r#"<a href="/e:f">d</a>"#));
+    assert!(output.contains(r#"<a href="">e</a>"#));
+}
+
+#[test]
+fn frontmatter_cannot_close_the_script() {
+    let output = convert("---\ntitle: </script><script>alert(1)\n---\n");
+    assert!(output.contains(r#""title":"\u003c/script>\u003cscript>alert(1)""#));
+    assert_eq!(output.matches("</script>").count(), 1);
+}
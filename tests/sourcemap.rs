@@ -0,0 +1,33 @@
+mod common;
+
+use common::options;
+use mdsvexrs::{Context, SvelteTarget};
+
+/// Mappings of each generated line.
+fn line_mappings(input: &str, target: SvelteTarget) -> (String, Vec<String>) {
+    let mut options = options();
+    options.layout = "L".to_string();
+    options.source_map = true;
+    options.target = target;
+    let output = Context::new(options).render(input).unwrap();
+    let mappings = output.map.unwrap().mappings.split(';').map(str::to_string).collect();
+    (output.code, mappings)
+}
+
+#[test]
+fn generated_script_is_not_mapped() {
+    for target in [SvelteTarget::Svelte4, SvelteTarget::Svelte5] {
+        let (code, mappings) = line_mappings("# A\n\nsome *text*\n", target);
+        let line = code.lines().position(|line| line.contains("import MDXLayout")).unwrap();
+        assert_eq!(mappings[line], "", "{code}");
+    }
+}
+
+#[test]
+fn document_script_is_mapped() {
+    let input = "<script>\nlet a = 1;\n</script>\n\n# A\n";
+    let (code, mappings) = line_mappings(input, SvelteTarget::Svelte4);
+    let line = code.lines().position(|line| line == "let a = 1;").unwrap();
+    // starts at the first column, in the next source line after the opening tag
+    assert!(mappings[line].starts_with("AAC"), "{}", mappings[line]);
+}